@@ -0,0 +1,254 @@
+use c3dio::prelude::*;
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
+use glob::Pattern;
+use grid::Grid;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::args::{file_arg, format_arg, jobs_arg, output_arg};
+use crate::batch::{find_files, finish_batch, job_count, run_batch};
+use crate::error::CliError;
+use crate::file_formats::table::{Table, TableFormat};
+
+pub(super) fn analog_command() -> Command {
+    Command::new("analog")
+        .about("Writes analog channels such as EMG, accelerometers and triggers from a C3D file")
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            format_arg()
+                .default_value("csv")
+                .help("The format to write: csv, sto or mot"),
+        )
+        .arg(
+            Arg::new("CHANNELS")
+                .short('c')
+                .long("channels")
+                .help("A comma separated list of channel labels or glob patterns such as \"EMG*\", all channels by default"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_analog_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let format = sub_matches.get_one::<String>("FORMAT").unwrap();
+    let format = TableFormat::from_str(format).map_err(CliError::Arguments)?;
+    let channels = match sub_matches.get_one::<String>("CHANNELS") {
+        Some(channels) => channels
+            .split(',')
+            .map(|channel| channel.trim().to_string())
+            .filter(|channel| !channel.is_empty())
+            .collect::<Vec<_>>(),
+        None => vec!["*".to_string()],
+    };
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let output: PathBuf = match output {
+        Some(output) => output.into(),
+        None => {
+            // set output to current directory
+            println!(
+                "{}",
+                "No output file was provided, writing to current directory".yellow()
+            );
+            std::env::current_dir().map_err(CliError::write)?
+        }
+    };
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        let columns = select_channels(&c3d, &channels).map_err(CliError::Arguments)?;
+        let output = match output.is_dir() {
+            true => {
+                let mut output = output.join(file.file_name().unwrap());
+                output.set_extension(format.to_string());
+                output
+            }
+            false => output.clone(),
+        };
+        progress.status(format!(
+            "Converting {} channels to {}",
+            columns.len(),
+            format.to_string().bright_yellow()
+        ));
+        analog_table(&c3d, &columns)
+            .write(output.clone(), format)
+            .map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+/// Returns the analog columns whose labels match any of the labels or glob patterns,
+/// in the order they are stored in the file. "all" selects every channel like "*".
+pub(crate) fn select_channels(c3d: &C3d, patterns: &[String]) -> Result<Vec<usize>, String> {
+    let labels = &c3d.analog.labels;
+    let mut selected = vec![false; c3d.analog.cols()];
+    for pattern in patterns {
+        let glob = match pattern.eq_ignore_ascii_case("all") {
+            true => "*",
+            false => pattern.as_str(),
+        };
+        let matcher = Pattern::new(glob)
+            .map_err(|e| format!("{} is not a valid channel pattern: {}", pattern, e))?;
+        let mut found = false;
+        for (column, label) in labels.iter().take(selected.len()).enumerate() {
+            // labels are compared exactly first so ones containing glob characters still match
+            if label.trim() == pattern || matcher.matches(label.trim()) {
+                selected[column] = true;
+                found = true;
+            }
+        }
+        if !found {
+            return Err(format!("{} does not match any analog channel", pattern));
+        }
+    }
+    Ok((0..selected.len())
+        .filter(|column| selected[*column])
+        .collect())
+}
+
+/// Builds a table of analog channels with a time column at the analog rate.
+/// c3dio applies the ANALOG:OFFSET, SCALE and GEN_SCALE parameters when loading,
+/// so the values are already in the units of ANALOG:UNITS.
+pub(crate) fn analog_table(c3d: &C3d, columns: &[usize]) -> Table {
+    let mut data = Grid::new(c3d.analog.rows(), 0);
+    // grid cannot take empty columns
+    if c3d.analog.rows() > 0 {
+        for column in columns {
+            data.push_col(c3d.analog.iter_col(*column).copied().collect());
+        }
+    }
+    let column_names = columns
+        .iter()
+        .map(|column| match c3d.analog.labels.get(*column) {
+            Some(label) => label.trim().to_string(),
+            None => format!("Channel_{}", column + 1),
+        })
+        .collect();
+    Table {
+        start_time: export_start_time(c3d),
+        data_rate: c3d.analog.rate as f64,
+        column_names,
+        data,
+    }
+}
+
+/// Appends a channel to the analog data, keeping the ANALOG parameters in step.
+/// The scale is chosen so the values fit when the file stores analog data as integers.
+pub(crate) fn add_analog_channel(
+    c3d: &mut C3d,
+    label: &str,
+    description: &str,
+    units: &str,
+    values: Vec<f64>,
+) -> Result<(), String> {
+    if values.is_empty() {
+        return Err(format!("{} has no samples to add", label));
+    }
+    if values.len() != analog_rows(c3d) {
+        return Err(format!(
+            "{} has {} samples but the file has {}",
+            label,
+            values.len(),
+            analog_rows(c3d)
+        ));
+    }
+    if c3d
+        .analog
+        .labels
+        .iter()
+        .any(|existing| existing.trim() == label)
+    {
+        return Err(format!(
+            "{} is already an analog channel in the file",
+            label
+        ));
+    }
+    let column = c3d.analog.cols();
+    if column == 0 {
+        // the first channel of a file without analog data sets the analog rate
+        c3d.analog.rate = analog_rate(c3d);
+        c3d.analog.samples_per_channel_per_frame =
+            c3d.analog.samples_per_channel_per_frame.max(1);
+        if c3d.analog.gen_scale == 0. {
+            c3d.analog.gen_scale = 1.;
+        }
+        if c3d.analog.bits == 0 {
+            c3d.analog.bits = 16;
+        }
+    }
+    // pad the per channel parameters in case the file left some of them short
+    c3d.analog.labels.resize(column, String::new());
+    c3d.analog.descriptions.resize(column, String::new());
+    c3d.analog.units.resize(column, String::new());
+    c3d.analog.scales.resize(column, 1.);
+    let peak = values
+        .iter()
+        .fold(0f64, |peak, value| peak.max(value.abs()));
+    let gen_scale = match c3d.analog.gen_scale {
+        gen_scale if gen_scale != 0. => gen_scale as f64,
+        _ => 1.,
+    };
+    let scale = match peak > 0. {
+        true => peak / 32000. / gen_scale,
+        false => 1.,
+    };
+    c3d.analog.analog.push_col(values);
+    c3d.analog.labels.push(label.to_string());
+    c3d.analog.descriptions.push(description.to_string());
+    c3d.analog.units.push(units.to_string());
+    c3d.analog.scales.push(scale as f32);
+    match &mut c3d.analog.offset {
+        AnalogOffset::Signed(offset) => {
+            offset.resize(column, 0);
+            offset.push(0);
+        }
+        // unsigned samples are centered on the middle of their range to keep negative values
+        AnalogOffset::Unsigned(offset) => {
+            offset.resize(column, 0);
+            offset.push(32768);
+        }
+    }
+    c3d.analog.samples_per_frame =
+        ((column + 1) * c3d.analog.samples_per_channel_per_frame as usize) as u16;
+    Ok(())
+}
+
+/// The number of analog samples in the file, or the number a first channel needs
+/// if the file has no analog data.
+pub(crate) fn analog_rows(c3d: &C3d) -> usize {
+    match c3d.analog.cols() {
+        0 => c3d.points.rows() * c3d.analog.samples_per_channel_per_frame.max(1) as usize,
+        _ => c3d.analog.rows(),
+    }
+}
+
+/// The analog rate, or the point rate if the file has no analog data.
+pub(crate) fn analog_rate(c3d: &C3d) -> f32 {
+    match c3d.analog.rate > 0. {
+        true => c3d.analog.rate,
+        false => {
+            c3d.points.frame_rate * c3d.analog.samples_per_channel_per_frame.max(1) as f32
+        }
+    }
+}
+
+/// The time in seconds of the first frame in exported files. TRC and STO files time
+/// frames from the frame number over the rate, one frame later than C3D events.
+pub(crate) fn export_start_time(c3d: &C3d) -> f64 {
+    c3d.points.first_frame as f64 / c3d.points.frame_rate as f64
+}
+
+/// The time in seconds of every analog sample on the time base of exported files.
+pub(crate) fn analog_times(c3d: &C3d) -> Vec<f64> {
+    let start = export_start_time(c3d);
+    let rate = analog_rate(c3d) as f64;
+    (0..analog_rows(c3d))
+        .map(|row| start + row as f64 / rate)
+        .collect()
+}
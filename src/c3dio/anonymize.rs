@@ -0,0 +1,528 @@
+//! Removes subject identifying metadata before data is shared.
+//!
+//! Rules name parameters by group and parameter, either of which may be a glob
+//! pattern, and say whether to remove them, blank them or replace their text with
+//! pseudonyms. Rules are read from a TOML or YAML file:
+//!
+//! ```toml
+//! [[rules]]
+//! group = "SUBJECTS"
+//! parameter = "NAMES"
+//! action = "pseudonym"
+//!
+//! [[rules]]
+//! group = "*"
+//! parameter = "NOTES"
+//! action = "blank"
+//! ```
+//!
+//! Pseudonyms come from a CSV or TOML mapping of original to pseudonym, which also
+//! renames the output files. Every change is written to an audit log.
+use c3dio::prelude::*;
+use c3dio::Event;
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
+use glob::Pattern;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::args::{file_arg, jobs_arg, output_arg};
+use crate::batch::{check_output, find_files, job_count, run_batch, split_batch, suffixed_name};
+use crate::error::CliError;
+use crate::marker_labels::read_label_map;
+use crate::parameters::all_parameters;
+use crate::run::{check_keys, load_document, string};
+
+pub(super) fn anonymize_command() -> Command {
+    Command::new("anonymize")
+        .about("Removes, blanks or pseudonymizes subject identifying parameters and file names of C3D files")
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            Arg::new("RULES")
+                .short('r')
+                .long("rules")
+                .help("A TOML or YAML file of group, parameter and action rules, the subject, event subject, edit history and notes parameters by default"),
+        )
+        .arg(
+            Arg::new("MAPPING")
+                .short('m')
+                .long("mapping")
+                .help("A CSV or TOML file mapping original names and IDs to pseudonyms"),
+        )
+        .arg(
+            Arg::new("AUDIT")
+                .long("audit")
+                .help("The CSV audit log to write, anonymize_audit.csv next to the output by default. It holds the original values, so keep it private"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_anonymize_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let (output, suffix): (PathBuf, &str) = match sub_matches.get_one::<String>("OUTPUT") {
+        Some(output) => (output.into(), ""),
+        None => {
+            // set output to current directory, named so the inputs are not replaced
+            println!(
+                "{}",
+                "No output file was provided, writing _anon files to current directory".yellow()
+            );
+            (std::env::current_dir().map_err(CliError::write)?, "_anon")
+        }
+    };
+    let rules = match sub_matches.get_one::<String>("RULES") {
+        Some(rules) => load_rules(Path::new(rules)).map_err(CliError::Arguments)?,
+        None => default_rules(),
+    };
+    let pseudonyms = match sub_matches.get_one::<String>("MAPPING") {
+        Some(mapping) => read_label_map(Path::new(mapping)).map_err(CliError::Arguments)?,
+        None => HashMap::new(),
+    };
+    if pseudonyms.is_empty()
+        && rules
+            .iter()
+            .any(|rule| rule.action == RuleAction::Pseudonym)
+    {
+        println!(
+            "{}",
+            "No pseudonyms were provided, pseudonym rules will blank their parameters".yellow()
+        );
+    }
+    let audit: PathBuf = match sub_matches.get_one::<String>("AUDIT") {
+        Some(audit) => audit.into(),
+        None => match output.is_dir() {
+            true => output.join("anonymize_audit.csv"),
+            false => output
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join("anonymize_audit.csv"),
+        },
+    };
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        let mut changes = anonymize(&mut c3d, &rules, &pseudonyms);
+        let output = match output.is_dir() {
+            true => {
+                let name = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let anonymized = replace_pseudonyms(&name, &pseudonyms).unwrap_or(name.clone());
+                if anonymized != name {
+                    changes.push(Change {
+                        group: String::new(),
+                        parameter: "FILE_NAME".to_string(),
+                        action: "pseudonymized",
+                        old: name,
+                        new: anonymized.clone(),
+                    });
+                }
+                output.join(suffixed_name(Path::new(&anonymized), suffix))
+            }
+            false => output.clone(),
+        };
+        check_output(file, &output)?;
+        c3d.write_path(output.clone()).map_err(CliError::write)?;
+        progress.status(format!(
+            "Wrote {} with {} changes",
+            output.to_string_lossy().green(),
+            changes.len()
+        ));
+        Ok((file.to_path_buf(), output, changes))
+    });
+    // the audit log covers the files that were written even if others failed
+    let total = results.len();
+    let (written, error) = split_batch(results);
+    write_audit(&audit, &written).map_err(CliError::write)?;
+    println!("Wrote {}", audit.to_string_lossy().green());
+    if total > 1 {
+        println!(
+            "Anonymized {} of {} files",
+            written.len().to_string().green(),
+            total
+        );
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// What a rule does to the parameters it matches.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum RuleAction {
+    Remove,
+    /// Replaces the text with empty strings of the same count.
+    Blank,
+    /// Replaces the names and IDs in the text with their pseudonyms, blanking text without one.
+    Pseudonym,
+}
+
+impl FromStr for RuleAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "remove" => Ok(RuleAction::Remove),
+            "blank" => Ok(RuleAction::Blank),
+            "pseudonym" => Ok(RuleAction::Pseudonym),
+            _ => Err(format!(
+                "{} is not a valid action, actions allowed: remove, blank, pseudonym",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for RuleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleAction::Remove => write!(f, "remove"),
+            RuleAction::Blank => write!(f, "blank"),
+            RuleAction::Pseudonym => write!(f, "pseudonym"),
+        }
+    }
+}
+
+/// Applies an action to the parameters whose group and name match the patterns, ignoring case.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Rule {
+    pub group: Pattern,
+    pub parameter: Pattern,
+    pub action: RuleAction,
+}
+
+impl Rule {
+    pub fn new(group: &str, parameter: &str, action: RuleAction) -> Result<Self, String> {
+        let pattern = |text: &str| {
+            Pattern::new(&text.trim().to_uppercase())
+                .map_err(|e| format!("{} is not a valid pattern: {}", text, e))
+        };
+        Ok(Rule {
+            group: pattern(group)?,
+            parameter: pattern(parameter)?,
+            action,
+        })
+    }
+
+    pub fn matches(&self, group: &str, parameter: &str) -> bool {
+        self.group.matches(&group.trim().to_uppercase())
+            && self.parameter.matches(&parameter.trim().to_uppercase())
+    }
+}
+
+/// The subject names and label prefixes, the subjects of events, the edit history
+/// and any notes.
+pub(crate) fn default_rules() -> Vec<Rule> {
+    [
+        ("SUBJECTS", "NAMES", RuleAction::Pseudonym),
+        ("SUBJECTS", "LABEL_PREFIXES", RuleAction::Pseudonym),
+        ("EVENT", "SUBJECTS", RuleAction::Pseudonym),
+        ("MANUFACTURER", "EDITED", RuleAction::Blank),
+        ("*", "NOTES", RuleAction::Blank),
+    ]
+    .iter()
+    .map(|(group, parameter, action)| Rule::new(group, parameter, *action).unwrap())
+    .collect()
+}
+
+/// Reads the `rules` list of a TOML or YAML file.
+pub(crate) fn load_rules(path: &Path) -> Result<Vec<Rule>, String> {
+    let document = load_document(path)?;
+    let document = document
+        .as_object()
+        .ok_or("The rules file must be a table with a list of rules".to_string())?;
+    check_keys(document, &["rules"], "The rules file")?;
+    let rules = document
+        .get("rules")
+        .and_then(|rules| rules.as_array())
+        .ok_or("The rules file has no list of rules".to_string())?;
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| {
+            let rule = rule
+                .as_object()
+                .ok_or(format!("Rule {} must be a table", i + 1))?;
+            check_keys(
+                rule,
+                &["group", "parameter", "action"],
+                &format!("Rule {}", i + 1),
+            )?;
+            let group = string(rule, "group")?.unwrap_or("*".to_string());
+            let parameter =
+                string(rule, "parameter")?.ok_or(format!("Rule {} has no parameter", i + 1))?;
+            let action = string(rule, "action")?.ok_or(format!("Rule {} has no action", i + 1))?;
+            Rule::new(&group, &parameter, RuleAction::from_str(&action)?)
+        })
+        .collect()
+}
+
+/// A parameter changed by a rule, for the audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Change {
+    pub group: String,
+    pub parameter: String,
+    pub action: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// Applies the first matching rule to every parameter of the file, including the ones
+/// c3dio parses into typed fields. Blanking and pseudonyms only change text parameters,
+/// and parameters c3dio needs to write the file are blanked rather than removed.
+pub(crate) fn anonymize(
+    c3d: &mut C3d,
+    rules: &[Rule],
+    pseudonyms: &HashMap<String, String>,
+) -> Vec<Change> {
+    let parameters = all_parameters(c3d);
+    let mut names = Vec::new();
+    for group in parameters.groups() {
+        if let Some(group_map) = parameters.get_group(group) {
+            names.extend(group_map.keys().map(|name| (group.clone(), name.clone())));
+        }
+    }
+    names.sort();
+    let mut changes = Vec::new();
+    for (group, name) in names {
+        let Some(rule) = rules.iter().find(|rule| rule.matches(&group, &name)) else {
+            continue;
+        };
+        let Some(old) = text_values(c3d, &group, &name) else {
+            // numbers are only ever removed
+            if rule.action == RuleAction::Remove && c3d.parameters.remove(&group, &name).is_some() {
+                changes.push(Change {
+                    group,
+                    parameter: name,
+                    action: "removed",
+                    old: String::new(),
+                    new: String::new(),
+                });
+            }
+            continue;
+        };
+        let (action, new) = match rule.action {
+            RuleAction::Remove if remove_parameter(c3d, &group, &name) => ("removed", None),
+            RuleAction::Remove | RuleAction::Blank => {
+                ("blanked", Some(vec![String::new(); old.len()]))
+            }
+            RuleAction::Pseudonym => {
+                let new = old
+                    .iter()
+                    .map(|value| match value.trim().is_empty() {
+                        true => String::new(),
+                        false => replace_pseudonyms(value, pseudonyms).unwrap_or_default(),
+                    })
+                    .collect::<Vec<_>>();
+                ("pseudonymized", Some(new))
+            }
+        };
+        if let Some(new) = &new {
+            if *new == old {
+                continue;
+            }
+            if !set_text_values(c3d, &group, &name, new.clone()) {
+                continue;
+            }
+        }
+        changes.push(Change {
+            group,
+            parameter: name,
+            action,
+            old: old.join("; "),
+            new: new.map(|new| new.join("; ")).unwrap_or_default(),
+        });
+    }
+    changes
+}
+
+/// Returns the pseudonym of a whole value, or the text with every name that has a
+/// pseudonym replaced, such as the subject in a "Name:" label prefix. Only whole
+/// names are replaced, so "Ann" is left alone inside "Annotation", and the text is
+/// scanned once so a pseudonym is never replaced again.
+/// Returns `None` if no name in the text has a pseudonym.
+pub(crate) fn replace_pseudonyms(
+    text: &str,
+    pseudonyms: &HashMap<String, String>,
+) -> Option<String> {
+    if let Some(pseudonym) = pseudonyms.get(text.trim()) {
+        return Some(pseudonym.clone());
+    }
+    // try longer names first so a name inside another is not replaced on its own
+    let mut names = pseudonyms
+        .keys()
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut previous: Option<char> = None;
+    'scan: while let Some(c) = rest.chars().next() {
+        // a name starts and ends where letters and digits do
+        if !previous.is_some_and(char::is_alphanumeric) {
+            for name in &names {
+                if let Some(after) = rest.strip_prefix(name.as_str()) {
+                    if !after.starts_with(char::is_alphanumeric) {
+                        replaced.push_str(&pseudonyms[*name]);
+                        previous = name.chars().last();
+                        rest = after;
+                        continue 'scan;
+                    }
+                }
+            }
+        }
+        replaced.push(c);
+        previous = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    match replaced != text {
+        true => Some(replaced),
+        false => None,
+    }
+}
+
+/// Returns the text of a parameter, or `None` if it is not text.
+fn text_values(c3d: &C3d, group: &str, name: &str) -> Option<Vec<String>> {
+    let events = |field: fn(&Event) -> &String| {
+        Some(
+            c3d.events
+                .iter()
+                .map(|event| field(event).clone())
+                .collect(),
+        )
+    };
+    match (group, name) {
+        ("POINT", "LABELS") => Some(c3d.points.labels.clone()),
+        ("POINT", "DESCRIPTIONS") => Some(c3d.points.descriptions.clone()),
+        ("ANALOG", "LABELS") => Some(c3d.analog.labels.clone()),
+        ("ANALOG", "DESCRIPTIONS") => Some(c3d.analog.descriptions.clone()),
+        ("ANALOG", "UNITS") => Some(c3d.analog.units.clone()),
+        ("EVENT", "LABELS") => events(|event| &event.label),
+        ("EVENT", "CONTEXTS") => events(|event| &event.context),
+        ("EVENT", "DESCRIPTIONS") => events(|event| &event.description),
+        ("EVENT", "SUBJECTS") => events(|event| &event.subject),
+        ("MANUFACTURER", "COMPANY") => c3d.manufacturer.company.clone().map(|text| vec![text]),
+        ("MANUFACTURER", "SOFTWARE") => c3d.manufacturer.software.clone().map(|text| vec![text]),
+        ("MANUFACTURER", "VERSION") => match &c3d.manufacturer.version {
+            Some(ManufacturerVersion::String(version)) => Some(vec![version.clone()]),
+            _ => None,
+        },
+        ("MANUFACTURER", "EDITED") => c3d.manufacturer.edited.clone(),
+        _ => {
+            let parameter = c3d.parameters.get(group, name)?;
+            match &parameter.data {
+                ParameterData::Char(_) if parameter.dimensions.len() > 1 => {
+                    Vec::<String>::try_from(parameter).ok()
+                }
+                ParameterData::Char(_) => String::try_from(parameter).ok().map(|text| vec![text]),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Replaces the text of a parameter, or returns false if it could not be stored.
+fn set_text_values(c3d: &mut C3d, group: &str, name: &str, values: Vec<String>) -> bool {
+    let mut set_events = |field: fn(&mut Event) -> &mut String| {
+        for (event, value) in c3d.events.iter_mut().zip(values.iter()) {
+            *field(event) = value.clone();
+        }
+    };
+    match (group, name) {
+        ("POINT", "LABELS") => c3d.points.labels = values,
+        ("POINT", "DESCRIPTIONS") => c3d.points.descriptions = values,
+        ("ANALOG", "LABELS") => c3d.analog.labels = values,
+        ("ANALOG", "DESCRIPTIONS") => c3d.analog.descriptions = values,
+        ("ANALOG", "UNITS") => c3d.analog.units = values,
+        ("EVENT", "LABELS") => set_events(|event| &mut event.label),
+        ("EVENT", "CONTEXTS") => set_events(|event| &mut event.context),
+        ("EVENT", "DESCRIPTIONS") => set_events(|event| &mut event.description),
+        ("EVENT", "SUBJECTS") => set_events(|event| &mut event.subject),
+        ("MANUFACTURER", "COMPANY") => c3d.manufacturer.company = values.into_iter().next(),
+        ("MANUFACTURER", "SOFTWARE") => c3d.manufacturer.software = values.into_iter().next(),
+        ("MANUFACTURER", "VERSION") => {
+            c3d.manufacturer.version = values.into_iter().next().map(ManufacturerVersion::String)
+        }
+        ("MANUFACTURER", "EDITED") => c3d.manufacturer.edited = Some(values),
+        _ => {
+            let Some(parameter) = c3d.parameters.get(group, name) else {
+                return false;
+            };
+            let description = parameter.description.clone();
+            let single = parameter.dimensions.len() <= 1;
+            let replacement = match single {
+                // a string parameter cannot be empty, so blank text is a single space
+                true => match values.concat() {
+                    text if text.is_empty() => Parameter::string(" ".to_string()).ok(),
+                    text => Parameter::string(text).ok(),
+                },
+                false => Some(Parameter::strings(values)),
+            };
+            match replacement {
+                Some(mut replacement) => {
+                    replacement.description = description;
+                    c3d.parameters.insert(group, name, replacement);
+                }
+                None => return false,
+            }
+        }
+    }
+    true
+}
+
+/// Removes a text parameter, or returns false if c3dio needs it to write the file.
+fn remove_parameter(c3d: &mut C3d, group: &str, name: &str) -> bool {
+    match (group, name) {
+        ("MANUFACTURER", "COMPANY") => c3d.manufacturer.company = None,
+        ("MANUFACTURER", "SOFTWARE") => c3d.manufacturer.software = None,
+        ("MANUFACTURER", "VERSION") => c3d.manufacturer.version = None,
+        ("MANUFACTURER", "EDITED") => c3d.manufacturer.edited = None,
+        ("POINT" | "ANALOG" | "EVENT", _) if !c3d.parameters.contains(group, name) => return false,
+        _ => return c3d.parameters.remove(group, name).is_some(),
+    }
+    true
+}
+
+/// Writes one row for every change with the input and output files it was made for.
+fn write_audit(
+    path: &Path,
+    files: &[(PathBuf, PathBuf, Vec<Change>)],
+) -> Result<(), std::io::Error> {
+    let mut file = BufWriter::new(std::fs::File::create(path)?);
+    writeln!(file, "file,output,group,parameter,action,old,new")?;
+    for (input, output, changes) in files {
+        for change in changes {
+            let fields = [
+                input.to_string_lossy().to_string(),
+                output.to_string_lossy().to_string(),
+                change.group.clone(),
+                change.parameter.clone(),
+                change.action.to_string(),
+                change.old.clone(),
+                change.new.clone(),
+            ];
+            let fields = fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>();
+            writeln!(file, "{}", fields.join(","))?;
+        }
+    }
+    file.flush()
+}
+
+/// Quotes a CSV field if it holds a delimiter, quote or line break.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
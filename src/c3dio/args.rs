@@ -20,4 +20,42 @@ pub(super) fn reference_arg() -> Arg {
         .short('r')
         .long("reference")
         .help("The reference file to use")
-}
\ No newline at end of file
+}
+pub(super) fn jobs_arg() -> Arg {
+    Arg::new("JOBS")
+        .short('j')
+        .long("jobs")
+        .value_parser(clap::value_parser!(usize))
+        .help("The number of files to process at once, every CPU by default")
+}
+
+pub(super) fn left_markers_arg() -> Arg {
+    Arg::new("LEFT_MARKERS")
+        .long("left-markers")
+        .default_value("LHEE,LTOE")
+        .help("The comma separated left heel and toe markers used to assign the foot")
+}
+
+pub(super) fn right_markers_arg() -> Arg {
+    Arg::new("RIGHT_MARKERS")
+        .long("right-markers")
+        .default_value("RHEE,RTOE")
+        .help("The comma separated right heel and toe markers used to assign the foot")
+}
+
+pub(super) fn assign_arg() -> Arg {
+    Arg::new("ASSIGN")
+        .long("assign")
+        .default_value("markers")
+        .value_parser(["markers", "events"])
+        .help("How plate contacts are assigned to a foot: the nearest foot markers, or the foot strike and foot off events of the file")
+}
+
+pub(super) fn error_format_arg() -> Arg {
+    Arg::new("ERROR_FORMAT")
+        .long("error-format")
+        .global(true)
+        .default_value("text")
+        .value_parser(["text", "json"])
+        .help("How errors are printed to stderr: text or json")
+}
@@ -0,0 +1,197 @@
+//! Runs the same work on many files at once with a progress bar and a summary,
+//! so one bad trial does not stop a batch of thousands.
+use colored::Colorize;
+use glob::glob;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::error::CliError;
+
+/// Expands a glob pattern into the files it matches, printing unreadable entries.
+/// Fails if the pattern is invalid or matches no files.
+pub(crate) fn find_files(pattern: &str) -> Result<Vec<PathBuf>, CliError> {
+    let files = glob(pattern).map_err(|e| CliError::Arguments(e.to_string()))?;
+    let files = files
+        .filter_map(|file| match file {
+            Ok(file) if file.is_file() => Some(file),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    match files.is_empty() {
+        true => Err(CliError::Arguments(format!("No files match {}", pattern))),
+        false => Ok(files),
+    }
+}
+
+/// The file name of `file` with `suffix` added to its stem, such as `walk_filled.c3d`.
+pub(crate) fn suffixed_name(file: &Path, suffix: &str) -> String {
+    let stem = file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match file.extension() {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    }
+}
+
+/// Fails if `output` is `file` itself, so a command never replaces the file it read.
+pub(crate) fn check_output(file: &Path, output: &Path) -> Result<(), CliError> {
+    match (file.canonicalize(), output.canonicalize()) {
+        (Ok(file), Ok(output)) if file == output => Err(CliError::Arguments(format!(
+            "{} is the input file, give another output so it is not replaced",
+            output.to_string_lossy()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Prints messages from the workers above the progress bar without breaking it.
+pub(crate) struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    /// Prints a message that should always be seen, such as a warning.
+    pub fn println(&self, message: impl AsRef<str>) {
+        self.bar.suspend(|| println!("{}", message.as_ref()));
+    }
+
+    /// Reports routine progress on a file, printed for a single file and shown
+    /// next to the bar otherwise so thousands of files do not flood the terminal.
+    pub fn status(&self, message: impl AsRef<str>) {
+        match self.bar.is_hidden() {
+            true => println!("{}", message.as_ref()),
+            false => self.bar.set_message(message.as_ref().to_string()),
+        }
+    }
+}
+
+/// The number of worker threads for `--jobs`, where 0 or none uses every CPU.
+pub(crate) fn job_count(jobs: Option<usize>) -> usize {
+    match jobs {
+        Some(jobs) if jobs > 0 => jobs,
+        _ => std::thread::available_parallelism()
+            .map(|jobs| jobs.get())
+            .unwrap_or(1),
+    }
+}
+
+/// Runs `work` on every file on up to `jobs` threads and returns the results in the
+/// order of `files`. Failures are printed as they happen and the rest carry on.
+pub(crate) fn run_batch<T, F>(
+    files: &[PathBuf],
+    jobs: usize,
+    work: F,
+) -> Vec<(PathBuf, Result<T, CliError>)>
+where
+    T: Send,
+    F: Fn(&Path, &Progress) -> Result<T, CliError> + Sync,
+{
+    // a single file prints just as it always has, without a bar
+    let bar = match files.len() > 1 {
+        true => ProgressBar::new(files.len() as u64).with_style(
+            ProgressStyle::with_template("{bar:40.cyan/blue} {pos}/{len} files {elapsed} {msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_bar()),
+        ),
+        false => ProgressBar::hidden(),
+    };
+    let progress = Progress { bar };
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<T, CliError>>>> =
+        Mutex::new((0..files.len()).map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                let result = work(file, &progress);
+                // the error of a single file is reported once by the caller
+                if let (Err(e), false) = (&result, progress.bar.is_hidden()) {
+                    progress.println(
+                        format!("{}: {}", file.to_string_lossy(), e)
+                            .red()
+                            .to_string(),
+                    );
+                }
+                if let Ok(mut results) = results.lock() {
+                    results[index] = Some(result);
+                }
+                progress.bar.inc(1);
+            });
+        }
+    });
+    progress.bar.finish_and_clear();
+    let results = results.into_inner().unwrap_or_default();
+    files
+        .iter()
+        .cloned()
+        .zip(results)
+        .map(|(file, result)| {
+            let result = result.unwrap_or(Err(CliError::Processing(
+                "The file was not processed".to_string(),
+            )));
+            (file, result)
+        })
+        .collect()
+}
+
+/// Splits the results into the values of the files that succeeded and an error for
+/// the ones that failed. A single file keeps its own error so its exit code says
+/// what went wrong.
+pub(crate) fn split_batch<T>(
+    results: Vec<(PathBuf, Result<T, CliError>)>,
+) -> (Vec<T>, Option<CliError>) {
+    let total = results.len();
+    let mut values = Vec::new();
+    let mut failures = Vec::new();
+    for (file, result) in results {
+        match result {
+            Ok(value) => values.push(value),
+            Err(e) => failures.push((file, e)),
+        }
+    }
+    let error = match (total, failures.pop()) {
+        (_, None) => None,
+        (1, Some((_, e))) => Some(e),
+        (_, Some(failure)) => {
+            failures.push(failure);
+            Some(CliError::Batch { total, failures })
+        }
+    };
+    (values, error)
+}
+
+/// Prints how many files succeeded when there was more than one and fails if any
+/// file failed.
+pub(crate) fn finish_batch<T>(
+    results: Vec<(PathBuf, Result<T, CliError>)>,
+) -> Result<Vec<T>, CliError> {
+    let total = results.len();
+    let (values, error) = split_batch(results);
+    if total > 1 {
+        let succeeded = format!("{} succeeded", values.len());
+        let failed = format!("{} failed", total - values.len());
+        println!(
+            "Processed {} files: {}, {}",
+            total,
+            succeeded.green(),
+            match error.is_none() {
+                true => failed.normal(),
+                false => failed.red(),
+            }
+        );
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(values),
+    }
+}
@@ -0,0 +1,391 @@
+use c3dio::prelude::*;
+use c3dio::Event;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::args::file_arg;
+use crate::error::CliError;
+use crate::gaps::is_gap;
+use crate::parameters::{all_parameters, parameter_data_json};
+
+pub(super) fn diff_command() -> Command {
+    Command::new("diff")
+        .about("Compares two C3D files and reports the differences, exiting with 1 if they differ")
+        .arg(file_arg().required(true))
+        .arg(
+            Arg::new("OTHER")
+                .required(true)
+                .help("The C3D file to compare against"),
+        )
+        .arg(
+            Arg::new("TOLERANCE")
+                .short('t')
+                .long("tolerance")
+                .default_value("0.000001")
+                .value_parser(clap::value_parser!(f64))
+                .help("The largest absolute numeric difference that is treated as equal"),
+        )
+        .arg(
+            Arg::new("JSON")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Print the differences as a JSON document"),
+        )
+}
+
+pub(super) fn process_diff_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let other = sub_matches.get_one::<String>("OTHER").unwrap();
+    let tolerance = *sub_matches.get_one::<f64>("TOLERANCE").unwrap();
+    let json = sub_matches.get_flag("JSON");
+    let mut files = Vec::new();
+    for path in [file, other] {
+        if !json {
+            println!("Opening {}", path.green());
+        }
+        files.push(C3d::load(path).map_err(CliError::load)?);
+    }
+    let diff = C3dDiff::new(&files[0], &files[1], tolerance);
+    if json {
+        let document = serde_json::to_string_pretty(&diff.json())
+            .map_err(|e| CliError::Processing(e.to_string()))?;
+        println!("{}", document);
+    } else {
+        print!("{}", diff.text());
+    }
+    match diff.is_empty() {
+        true => Ok(()),
+        false => Err(CliError::Findings("The files differ".to_string())),
+    }
+}
+
+/// A value that differs between the two files, `None` where it is missing from one of them.
+#[derive(Debug, Clone, PartialEq)]
+struct Change {
+    name: String,
+    a: Option<Value>,
+    b: Option<Value>,
+}
+
+impl Change {
+    fn json(&self) -> Value {
+        json!({ "name": self.name, "a": self.a, "b": self.b })
+    }
+
+    fn text(&self) -> String {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(missing)".to_string(),
+        };
+        format!("  {}: {} -> {}\n", self.name, show(&self.a), show(&self.b))
+    }
+}
+
+/// The numeric difference of one marker or analog channel present in both files.
+#[derive(Debug, Clone, PartialEq)]
+struct ChannelDiff {
+    label: String,
+    max_abs: f64,
+    rms: f64,
+    /// Frames where the marker is missing in one file but not the other.
+    gap_mismatches: usize,
+}
+
+impl ChannelDiff {
+    fn json(&self) -> Value {
+        json!({
+            "label": self.label,
+            "max_abs": self.max_abs,
+            "rms": self.rms,
+            "gap_mismatches": self.gap_mismatches,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct C3dDiff {
+    header: Vec<Change>,
+    parameters: Vec<Change>,
+    labels: Vec<Change>,
+    events: Vec<Change>,
+    points: Vec<ChannelDiff>,
+    analog: Vec<ChannelDiff>,
+}
+
+impl C3dDiff {
+    fn new(a: &C3d, b: &C3d, tolerance: f64) -> Self {
+        C3dDiff {
+            header: diff_values(header_values(a), header_values(b), tolerance),
+            parameters: diff_values(parameter_values(a), parameter_values(b), tolerance),
+            labels: diff_labels(a, b),
+            events: diff_values(event_values(a), event_values(b), tolerance),
+            points: diff_points(a, b, tolerance),
+            analog: diff_analog(a, b, tolerance),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.parameters.is_empty()
+            && self.labels.is_empty()
+            && self.events.is_empty()
+            && self.points.is_empty()
+            && self.analog.is_empty()
+    }
+
+    fn text(&self) -> String {
+        if self.is_empty() {
+            return "The files are identical\n".to_string();
+        }
+        let mut text = String::new();
+        for (title, changes) in [
+            ("Header", &self.header),
+            ("Parameters", &self.parameters),
+            ("Labels", &self.labels),
+            ("Events", &self.events),
+        ] {
+            if !changes.is_empty() {
+                text.push_str(&format!("{}\n", title));
+                changes
+                    .iter()
+                    .for_each(|change| text.push_str(&change.text()));
+            }
+        }
+        for (title, channels) in [("Markers", &self.points), ("Analog", &self.analog)] {
+            if !channels.is_empty() {
+                text.push_str(&format!(
+                    "{}\n  {:<20} {:>12} {:>12} {:>8}\n",
+                    title, "Label", "Max abs", "RMS", "Gaps"
+                ));
+                for channel in channels {
+                    text.push_str(&format!(
+                        "  {:<20} {:>12.6} {:>12.6} {:>8}\n",
+                        channel.label, channel.max_abs, channel.rms, channel.gap_mismatches
+                    ));
+                }
+            }
+        }
+        text
+    }
+
+    fn json(&self) -> Value {
+        let changes = |changes: &Vec<Change>| changes.iter().map(|c| c.json()).collect::<Vec<_>>();
+        let channels = |c: &Vec<ChannelDiff>| c.iter().map(|c| c.json()).collect::<Vec<_>>();
+        json!({
+            "identical": self.is_empty(),
+            "header": changes(&self.header),
+            "parameters": changes(&self.parameters),
+            "labels": changes(&self.labels),
+            "events": changes(&self.events),
+            "points": channels(&self.points),
+            "analog": channels(&self.analog),
+        })
+    }
+}
+
+fn header_values(c3d: &C3d) -> BTreeMap<String, Value> {
+    BTreeMap::from([
+        ("first_frame".to_string(), json!(c3d.points.first_frame)),
+        ("last_frame".to_string(), json!(c3d.points.last_frame)),
+        ("frames".to_string(), json!(c3d.points.rows())),
+        ("point_rate".to_string(), json!(c3d.points.frame_rate)),
+        ("scale_factor".to_string(), json!(c3d.points.scale_factor)),
+        (
+            "max_interpolation_gap".to_string(),
+            json!(c3d.points.max_interpolation_gap),
+        ),
+        ("points".to_string(), json!(c3d.points.cols())),
+        ("analog_channels".to_string(), json!(c3d.analog.cols())),
+        ("analog_rate".to_string(), json!(c3d.analog.rate)),
+        (
+            "analog_samples_per_frame".to_string(),
+            json!(c3d.analog.samples_per_channel_per_frame),
+        ),
+    ])
+}
+
+fn parameter_values(c3d: &C3d) -> BTreeMap<String, Value> {
+    let parameters = all_parameters(c3d);
+    let mut values = BTreeMap::new();
+    for group in parameters.groups() {
+        if let Some(group_map) = parameters.get_group(group) {
+            for (name, parameter) in group_map.iter() {
+                values.insert(
+                    format!("{}:{}", group, name),
+                    parameter_data_json(parameter),
+                );
+            }
+        }
+    }
+    values
+}
+
+/// Keys events by context, label and their order within that pair, so a moved event shows
+/// up as a changed time rather than a removal and an addition.
+fn event_values(c3d: &C3d) -> BTreeMap<String, Value> {
+    let mut events: Vec<&Event> = c3d.events.iter().collect();
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let mut values = BTreeMap::new();
+    let mut counts: BTreeMap<(String, String), usize> = BTreeMap::new();
+    for event in events {
+        let key = (
+            event.context.trim().to_string(),
+            event.label.trim().to_string(),
+        );
+        let count = counts.entry(key.clone()).or_insert(0);
+        *count += 1;
+        values.insert(format!("{} {} #{}", key.0, key.1, count), json!(event.time));
+    }
+    values
+}
+
+fn diff_values(
+    a: BTreeMap<String, Value>,
+    b: BTreeMap<String, Value>,
+    tolerance: f64,
+) -> Vec<Change> {
+    let mut names: Vec<&String> = a.keys().chain(b.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| match (a.get(name), b.get(name)) {
+            (Some(x), Some(y)) if values_equal(x, y, tolerance) => None,
+            (x, y) => Some(Change {
+                name: name.clone(),
+                a: x.cloned(),
+                b: y.cloned(),
+            }),
+        })
+        .collect()
+}
+
+/// Compares JSON values, allowing numbers to differ by the tolerance.
+fn values_equal(a: &Value, b: &Value, tolerance: f64) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => (x - y).abs() <= tolerance,
+            _ => x == y,
+        },
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .zip(y.iter())
+                    .all(|(x, y)| values_equal(x, y, tolerance))
+        }
+        _ => a == b,
+    }
+}
+
+fn diff_labels(a: &C3d, b: &C3d) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (kind, a_labels, b_labels) in [
+        ("point", &a.points.labels, &b.points.labels),
+        ("analog", &a.analog.labels, &b.analog.labels),
+    ] {
+        let a_labels: Vec<&str> = a_labels.iter().map(|l| l.trim()).collect();
+        let b_labels: Vec<&str> = b_labels.iter().map(|l| l.trim()).collect();
+        for label in a_labels.iter().filter(|l| !b_labels.contains(l)) {
+            changes.push(Change {
+                name: format!("{} {}", kind, label),
+                a: Some(json!(label)),
+                b: None,
+            });
+        }
+        for label in b_labels.iter().filter(|l| !a_labels.contains(l)) {
+            changes.push(Change {
+                name: format!("{} {}", kind, label),
+                a: None,
+                b: Some(json!(label)),
+            });
+        }
+        let common_a: Vec<&&str> = a_labels.iter().filter(|l| b_labels.contains(l)).collect();
+        let common_b: Vec<&&str> = b_labels.iter().filter(|l| a_labels.contains(l)).collect();
+        if common_a != common_b {
+            changes.push(Change {
+                name: format!("{} label order", kind),
+                a: Some(json!(common_a)),
+                b: Some(json!(common_b)),
+            });
+        }
+    }
+    changes
+}
+
+/// Compares markers with the same label over the frames both files have.
+fn diff_points(a: &C3d, b: &C3d, tolerance: f64) -> Vec<ChannelDiff> {
+    let rows = a.points.rows().min(b.points.rows());
+    let mut diffs = Vec::new();
+    for (a_column, label) in a.points.labels.iter().enumerate().take(a.points.cols()) {
+        let b_column = match b
+            .points
+            .labels
+            .iter()
+            .position(|l| l.trim() == label.trim())
+        {
+            Some(column) if column < b.points.cols() => column,
+            _ => continue,
+        };
+        let mut differences = Vec::new();
+        let mut gap_mismatches = 0;
+        for row in 0..rows {
+            let x = &a.points[(row, a_column)];
+            let y = &b.points[(row, b_column)];
+            match (is_gap(x), is_gap(y)) {
+                (false, false) => {
+                    for axis in 0..3 {
+                        differences.push((x[axis] - y[axis]) as f64);
+                    }
+                }
+                (true, true) => {}
+                _ => gap_mismatches += 1,
+            }
+        }
+        let diff = channel_diff(label.trim(), &differences, gap_mismatches);
+        if diff.max_abs > tolerance || diff.gap_mismatches > 0 {
+            diffs.push(diff);
+        }
+    }
+    diffs
+}
+
+/// Compares analog channels with the same label over the samples both files have.
+fn diff_analog(a: &C3d, b: &C3d, tolerance: f64) -> Vec<ChannelDiff> {
+    let rows = a.analog.rows().min(b.analog.rows());
+    let mut diffs = Vec::new();
+    for (a_column, label) in a.analog.labels.iter().enumerate().take(a.analog.cols()) {
+        let b_column = match b
+            .analog
+            .labels
+            .iter()
+            .position(|l| l.trim() == label.trim())
+        {
+            Some(column) if column < b.analog.cols() => column,
+            _ => continue,
+        };
+        let differences: Vec<f64> = (0..rows)
+            .map(|row| a.analog[(row, a_column)] - b.analog[(row, b_column)])
+            .collect();
+        let diff = channel_diff(label.trim(), &differences, 0);
+        if diff.max_abs > tolerance {
+            diffs.push(diff);
+        }
+    }
+    diffs
+}
+
+fn channel_diff(label: &str, differences: &[f64], gap_mismatches: usize) -> ChannelDiff {
+    let max_abs = differences.iter().fold(0., |max: f64, d| max.max(d.abs()));
+    let rms = match differences.len() {
+        0 => 0.,
+        n => (differences.iter().map(|d| d * d).sum::<f64>() / n as f64).sqrt(),
+    };
+    ChannelDiff {
+        label: label.to_string(),
+        max_abs,
+        rms,
+        gap_mismatches,
+    }
+}
@@ -0,0 +1,391 @@
+use c3dio::prelude::*;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::analog::{add_analog_channel, analog_table, select_channels};
+use crate::args::{file_arg, format_arg, jobs_arg, output_arg};
+use crate::batch::{check_output, find_files, finish_batch, job_count, run_batch, suffixed_name};
+use crate::error::CliError;
+use crate::file_formats::table::TableFormat;
+use crate::filter::Butterworth;
+
+pub(super) fn emg_command() -> Command {
+    Command::new("emg")
+        .about("Processes EMG channels into linear envelopes, optionally normalized to MVC")
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            format_arg()
+                .default_value("csv")
+                .help("The format to write: csv, sto, mot or c3d (adds the envelopes as analog channels)"),
+        )
+        .arg(
+            Arg::new("CHANNELS")
+                .short('c')
+                .long("channels")
+                .required(true)
+                .help("A comma separated list of EMG channel labels or glob patterns such as \"EMG*\""),
+        )
+        .arg(
+            Arg::new("KEEP_DC")
+                .long("keep-dc")
+                .action(ArgAction::SetTrue)
+                .help("Skip removing the mean of each channel"),
+        )
+        .arg(
+            Arg::new("BAND_PASS")
+                .short('b')
+                .long("band-pass")
+                .default_value("20,450")
+                .help("The band-pass cutoffs in Hz as \"low,high\", or \"none\" to skip it"),
+        )
+        .arg(
+            Arg::new("ORDER")
+                .long("order")
+                .default_value("4")
+                .value_parser(clap::value_parser!(usize))
+                .help("The filter order of each pass, the forward-backward pass doubles it"),
+        )
+        .arg(
+            Arg::new("NO_RECTIFY")
+                .long("no-rectify")
+                .action(ArgAction::SetTrue)
+                .help("Skip full-wave rectification"),
+        )
+        .arg(
+            Arg::new("ENVELOPE")
+                .short('e')
+                .long("envelope")
+                .default_value("low-pass")
+                .help("The linear envelope to take: low-pass, rms or none"),
+        )
+        .arg(
+            Arg::new("ENVELOPE_CUTOFF")
+                .long("envelope-cutoff")
+                .default_value("6")
+                .value_parser(clap::value_parser!(f64))
+                .help("The cutoff frequency in Hz of the low-pass envelope"),
+        )
+        .arg(
+            Arg::new("WINDOW")
+                .short('w')
+                .long("window")
+                .default_value("50")
+                .value_parser(clap::value_parser!(f64))
+                .help("The window length in milliseconds of the RMS envelope"),
+        )
+        .arg(
+            Arg::new("MVC")
+                .long("mvc")
+                .help("A maximum voluntary contraction trial to normalize each channel to its peak"),
+        )
+        .arg(
+            Arg::new("SUFFIX")
+                .long("suffix")
+                .default_value("_ENV")
+                .help("The suffix added to the labels of new channels in c3d output"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_emg_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let format = sub_matches.get_one::<String>("FORMAT").unwrap();
+    let format = EmgOutputFileTypes::from_str(format).map_err(CliError::Arguments)?;
+    let channels = sub_matches
+        .get_one::<String>("CHANNELS")
+        .unwrap()
+        .split(',')
+        .map(|channel| channel.trim().to_string())
+        .filter(|channel| !channel.is_empty())
+        .collect::<Vec<_>>();
+    let pipeline = pipeline_from_matches(&sub_matches).map_err(CliError::Arguments)?;
+    let suffix = sub_matches.get_one::<String>("SUFFIX").unwrap();
+    let mvc = match sub_matches.get_one::<String>("MVC") {
+        Some(mvc) => {
+            println!("Opening MVC trial {}", mvc.green());
+            Some(C3d::load(mvc).map_err(CliError::load)?)
+        }
+        None => None,
+    };
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let (output, name_suffix): (PathBuf, &str) = match output {
+        Some(output) => (output.into(), ""),
+        None => {
+            // set output to current directory, named so the inputs are not replaced
+            println!(
+                "{}",
+                "No output file was provided, writing _emg files to current directory".yellow()
+            );
+            (std::env::current_dir().map_err(CliError::write)?, "_emg")
+        }
+    };
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        let columns = select_channels(&c3d, &channels).map_err(CliError::Arguments)?;
+        let mut envelopes = process_emg(&c3d, &columns, &pipeline)?;
+        if let Some(mvc) = &mvc {
+            normalize_to_mvc(&c3d, &columns, &mut envelopes, mvc, &pipeline)?;
+        }
+        let output = match output.is_dir() {
+            true => {
+                let mut output = output.join(suffixed_name(file, name_suffix));
+                output.set_extension(format.to_string());
+                output
+            }
+            false => output.clone(),
+        };
+        check_output(file, &output)?;
+        progress.status(format!(
+            "Processed {} channels, writing {}",
+            columns.len(),
+            format.to_string().bright_yellow()
+        ));
+        match format {
+            EmgOutputFileTypes::C3d => {
+                for (column, values) in columns.iter().zip(envelopes) {
+                    let label = format!("{}{}", c3d.analog.labels[*column].trim(), suffix);
+                    let units = match mvc {
+                        Some(_) => "%MVC".to_string(),
+                        None => c3d.analog.units.get(*column).cloned().unwrap_or_default(),
+                    };
+                    add_analog_channel(&mut c3d, &label, "EMG envelope", &units, values)?;
+                }
+                c3d.write_path(output.clone()).map_err(CliError::write)?;
+            }
+            EmgOutputFileTypes::Table(table_format) => {
+                // the raw channels are not written, so the envelopes can replace them
+                for (column, values) in columns.iter().zip(envelopes) {
+                    for (row, value) in values.into_iter().enumerate() {
+                        c3d.analog[(row, *column)] = value;
+                    }
+                }
+                analog_table(&c3d, &columns)
+                    .write(output.clone(), table_format)
+                    .map_err(CliError::write)?;
+            }
+        }
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum EmgOutputFileTypes {
+    C3d,
+    Table(TableFormat),
+}
+
+impl FromStr for EmgOutputFileTypes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "c3d" => Ok(EmgOutputFileTypes::C3d),
+            _ => TableFormat::from_str(s)
+                .map(EmgOutputFileTypes::Table)
+                .map_err(|_| {
+                    format!(
+                        "{} is not a valid output file type, types allowed: .csv, .sto, .mot, .c3d",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl Display for EmgOutputFileTypes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmgOutputFileTypes::C3d => write!(f, "c3d"),
+            EmgOutputFileTypes::Table(format) => write!(f, "{}", format),
+        }
+    }
+}
+
+/// The last step of the EMG pipeline, which smooths the rectified signal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Envelope {
+    /// A zero-lag low-pass Butterworth filter with the cutoff in Hz.
+    LowPass(f64),
+    /// A moving root mean square with the window length in seconds.
+    Rms(f64),
+    None,
+}
+
+/// The steps applied to each EMG channel, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EmgPipeline {
+    pub remove_dc: bool,
+    /// The high-pass and low-pass cutoffs in Hz.
+    pub band_pass: Option<(f64, f64)>,
+    pub order: usize,
+    pub rectify: bool,
+    pub envelope: Envelope,
+}
+
+impl Default for EmgPipeline {
+    fn default() -> Self {
+        EmgPipeline {
+            remove_dc: true,
+            band_pass: Some((20., 450.)),
+            order: 4,
+            rectify: true,
+            envelope: Envelope::LowPass(6.),
+        }
+    }
+}
+
+fn pipeline_from_matches(sub_matches: &ArgMatches) -> Result<EmgPipeline, String> {
+    let band_pass = sub_matches.get_one::<String>("BAND_PASS").unwrap();
+    let band_pass = match band_pass.trim().to_lowercase().as_str() {
+        "none" => None,
+        _ => {
+            let cutoffs = band_pass
+                .split(',')
+                .map(|cutoff| cutoff.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{} is not a list of cutoffs: {}", band_pass, e))?;
+            match cutoffs[..] {
+                [low, high] if low < high => Some((low, high)),
+                _ => {
+                    return Err(format!(
+                        "{} must be two cutoffs with the lower one first, for example \"20,450\"",
+                        band_pass
+                    ))
+                }
+            }
+        }
+    };
+    let envelope = sub_matches.get_one::<String>("ENVELOPE").unwrap();
+    let envelope = match envelope.trim().to_lowercase().as_str() {
+        "low-pass" | "lowpass" => {
+            Envelope::LowPass(*sub_matches.get_one::<f64>("ENVELOPE_CUTOFF").unwrap())
+        }
+        "rms" => Envelope::Rms(*sub_matches.get_one::<f64>("WINDOW").unwrap() / 1000.),
+        "none" => Envelope::None,
+        _ => {
+            return Err(format!(
+                "{} is not a valid envelope, envelopes allowed: low-pass, rms, none",
+                envelope
+            ))
+        }
+    };
+    Ok(EmgPipeline {
+        remove_dc: !sub_matches.get_flag("KEEP_DC"),
+        band_pass,
+        order: *sub_matches.get_one::<usize>("ORDER").unwrap(),
+        rectify: !sub_matches.get_flag("NO_RECTIFY"),
+        envelope,
+    })
+}
+
+impl EmgPipeline {
+    /// Runs every step on one channel sampled at `rate`.
+    pub fn apply(&self, values: &mut [f64], rate: f64) -> Result<(), String> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        if self.remove_dc {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter_mut().for_each(|value| *value -= mean);
+        }
+        if let Some((low, high)) = self.band_pass {
+            Butterworth::high_pass(self.order, low, rate)?.filtfilt(values);
+            Butterworth::low_pass(self.order, high, rate)?.filtfilt(values);
+        }
+        if self.rectify {
+            values.iter_mut().for_each(|value| *value = value.abs());
+        }
+        match self.envelope {
+            Envelope::LowPass(cutoff) => {
+                Butterworth::low_pass(self.order, cutoff, rate)?.filtfilt(values)
+            }
+            Envelope::Rms(window) => moving_rms(values, (window * rate).round() as usize),
+            Envelope::None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Replaces each value with the root mean square of the window centered on it,
+/// shrinking the window at the ends of the data.
+fn moving_rms(values: &mut [f64], window: usize) {
+    let half = window / 2;
+    let mut sums = Vec::with_capacity(values.len() + 1);
+    sums.push(0.);
+    for value in values.iter() {
+        sums.push(sums[sums.len() - 1] + value * value);
+    }
+    for (i, value) in values.iter_mut().enumerate() {
+        let start = i.saturating_sub(half);
+        let end = (i + half + 1).min(sums.len() - 1);
+        *value = ((sums[end] - sums[start]) / (end - start) as f64)
+            .max(0.)
+            .sqrt();
+    }
+}
+
+/// Runs the pipeline on the analog columns and returns the processed channels.
+pub(crate) fn process_emg(
+    c3d: &C3d,
+    columns: &[usize],
+    pipeline: &EmgPipeline,
+) -> Result<Vec<Vec<f64>>, String> {
+    columns
+        .iter()
+        .map(|column| {
+            let mut values: Vec<f64> = c3d.analog.iter_col(*column).copied().collect();
+            pipeline.apply(&mut values, c3d.analog.rate as f64)?;
+            Ok(values)
+        })
+        .collect()
+}
+
+/// Scales each channel to a percentage of the peak of the same channel in a maximum
+/// voluntary contraction trial processed with the same pipeline.
+pub(crate) fn normalize_to_mvc(
+    c3d: &C3d,
+    columns: &[usize],
+    envelopes: &mut [Vec<f64>],
+    mvc: &C3d,
+    pipeline: &EmgPipeline,
+) -> Result<(), String> {
+    let labels = columns
+        .iter()
+        .map(|column| c3d.analog.labels[*column].trim().to_string())
+        .collect::<Vec<_>>();
+    let mvc_columns = labels
+        .iter()
+        .map(|label| {
+            mvc.analog
+                .labels
+                .iter()
+                .position(|mvc_label| mvc_label.trim() == label)
+                .filter(|column| *column < mvc.analog.cols())
+                .ok_or(format!(
+                    "{} is not an analog channel in the MVC trial",
+                    label
+                ))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mvc_envelopes = process_emg(mvc, &mvc_columns, pipeline)?;
+    for ((values, mvc_values), label) in envelopes.iter_mut().zip(mvc_envelopes).zip(labels) {
+        let peak = mvc_values
+            .iter()
+            .fold(0f64, |peak, value| peak.max(value.abs()));
+        if peak <= 0. {
+            return Err(format!("{} has no activity in the MVC trial", label));
+        }
+        values.iter_mut().for_each(|value| *value *= 100. / peak);
+    }
+    Ok(())
+}
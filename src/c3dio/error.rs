@@ -0,0 +1,168 @@
+//! The errors commands return, each with its own exit code so scripts can tell
+//! bad arguments from unreadable files, failed writes and partial batches.
+use clap::ArgMatches;
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub(crate) enum CliError {
+    /// The command found problems in the files, such as validation warnings or differences.
+    Findings(String),
+    /// An argument or an options file could not be used.
+    Arguments(String),
+    /// A C3D or other input file could not be read.
+    Load(String),
+    /// An output file could not be written.
+    Write(String),
+    /// The data could not be processed, for example a filter cutoff above the Nyquist frequency.
+    Processing(String),
+    /// Validation found errors in the files.
+    Invalid(String),
+    /// Some of the files of a batch failed.
+    Batch {
+        total: usize,
+        failures: Vec<(PathBuf, CliError)>,
+    },
+}
+
+impl CliError {
+    pub fn load(e: impl Display) -> Self {
+        CliError::Load(e.to_string())
+    }
+
+    pub fn write(e: impl Display) -> Self {
+        CliError::Write(e.to_string())
+    }
+
+    /// Puts `context` in front of the message, keeping the kind and exit code.
+    pub fn context(self, context: impl Display) -> Self {
+        let message = |message: String| format!("{}: {}", context, message);
+        match self {
+            CliError::Findings(m) => CliError::Findings(message(m)),
+            CliError::Arguments(m) => CliError::Arguments(message(m)),
+            CliError::Load(m) => CliError::Load(message(m)),
+            CliError::Write(m) => CliError::Write(message(m)),
+            CliError::Processing(m) => CliError::Processing(message(m)),
+            CliError::Invalid(m) => CliError::Invalid(message(m)),
+            CliError::Batch { .. } => self,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Findings(_) => 1,
+            CliError::Arguments(_) => 2,
+            CliError::Load(_) => 3,
+            CliError::Write(_) => 4,
+            CliError::Processing(_) => 5,
+            CliError::Batch { .. } => 6,
+            CliError::Invalid(_) => 7,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            CliError::Findings(_) => "findings",
+            CliError::Arguments(_) => "arguments",
+            CliError::Load(_) => "load",
+            CliError::Write(_) => "write",
+            CliError::Processing(_) => "processing",
+            CliError::Batch { .. } => "batch",
+            CliError::Invalid(_) => "invalid",
+        }
+    }
+
+    fn json(&self) -> Value {
+        let mut document = json!({
+            "error": self.kind(),
+            "code": self.exit_code(),
+            "message": self.to_string(),
+        });
+        if let CliError::Batch { total, failures } = self {
+            document["total"] = json!(total);
+            document["failures"] = failures
+                .iter()
+                .map(|(file, e)| {
+                    let mut failure = e.json();
+                    failure["file"] = json!(file.to_string_lossy());
+                    failure
+                })
+                .collect();
+        }
+        document
+    }
+
+    /// Prints the error to stderr in the requested format.
+    pub fn report(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Text => {
+                eprintln!("{}", self.to_string().red());
+                if let CliError::Batch { failures, .. } = self {
+                    for (file, e) in failures {
+                        eprintln!("  {} {}", file.to_string_lossy().red(), e);
+                    }
+                }
+            }
+            ErrorFormat::Json => match serde_json::to_string(&self.json()) {
+                Ok(document) => eprintln!("{}", document),
+                Err(e) => eprintln!("{}", e),
+            },
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Findings(message)
+            | CliError::Arguments(message)
+            | CliError::Load(message)
+            | CliError::Write(message)
+            | CliError::Processing(message)
+            | CliError::Invalid(message) => write!(f, "{}", message),
+            CliError::Batch { total, failures } => {
+                write!(f, "{} of {} files failed", failures.len(), total)
+            }
+        }
+    }
+}
+
+/// Errors from the processing functions, which report problems as strings.
+/// Errors caused by the arguments are mapped to `CliError::Arguments` where they happen.
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::Processing(message)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub(crate) enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "{} is not a valid error format, formats allowed: text, json",
+                s
+            )),
+        }
+    }
+}
+
+pub(super) fn error_format(matches: &ArgMatches) -> ErrorFormat {
+    matches
+        .get_one::<String>("ERROR_FORMAT")
+        .and_then(|format| ErrorFormat::from_str(format).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,464 @@
+use c3dio::prelude::*;
+use c3dio::Event;
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use crate::args::{file_arg, left_markers_arg, output_arg, right_markers_arg};
+use crate::error::CliError;
+use crate::gait::{
+    detect_force_events, detect_kinematic_events, write_gait_events, FootAssignment, FootMarkers, ForceDetection,
+    KinematicDetection, KinematicMethod,
+};
+use crate::trim::{frame_time, time_frame};
+
+/// The number of events the C3D header can hold.
+/// c3dio writes every event into the header and only reads event times from it,
+/// so files with more cannot be written or read back.
+pub(crate) const MAX_HEADER_EVENTS: usize = 18;
+
+pub(super) fn events_command() -> Command {
+    Command::new("events")
+        .about("Lists and edits the events stored in a C3D file")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("Prints the events of a C3D file")
+                .arg(file_arg().required(true))
+                .arg(
+                    Arg::new("JSON")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the events as a JSON document"),
+                ),
+        )
+        .subcommand(
+            Command::new("add")
+                .about("Adds an event at a time or frame")
+                .arg(file_arg().required(true))
+                .arg(output_arg().help("The output file to write to, the input file by default"))
+                .arg(label_arg().required(true))
+                .arg(context_arg().default_value("General"))
+                .arg(time_arg())
+                .arg(frame_arg())
+                .group(ArgGroup::new("WHEN").args(["TIME", "FRAME"]).required(true)),
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Removes the events that match all of the given filters")
+                .arg(file_arg().required(true))
+                .arg(output_arg().help("The output file to write to, the input file by default"))
+                .arg(label_arg())
+                .arg(context_arg())
+                .arg(time_arg())
+                .arg(frame_arg())
+                .group(
+                    ArgGroup::new("FILTER")
+                        .args(["LABEL", "CONTEXT", "TIME", "FRAME"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about(
+                    "Adds events from a CSV file of context,label,time or context,label,frame rows",
+                )
+                .arg(file_arg().required(true))
+                .arg(
+                    Arg::new("CSV")
+                        .required(true)
+                        .help("The CSV file with the events to import"),
+                )
+                .arg(output_arg().help("The output file to write to, the input file by default"))
+                .arg(
+                    Arg::new("REPLACE")
+                        .long("replace")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove the existing events before importing"),
+                ),
+        )
+        .subcommand(
+            Command::new("detect")
+                .about("Detects foot strikes and foot offs from force plates or marker trajectories")
+                .arg(file_arg().required(true))
+                .arg(output_arg().help("The output file to write to, the input file by default"))
+                .arg(
+                    Arg::new("METHOD")
+                        .short('m')
+                        .long("method")
+                        .default_value("force")
+                        .help("The detection method: force, coordinate or velocity"),
+                )
+                .arg(
+                    Arg::new("THRESHOLD")
+                        .long("threshold")
+                        .default_value("20")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("The vertical force in newtons above which a foot is on a plate, at least 10 above the hysteresis"),
+                )
+                .arg(
+                    Arg::new("HYSTERESIS")
+                        .long("hysteresis")
+                        .default_value("5")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("How far in newtons the force must drop below the threshold for a foot off"),
+                )
+                .arg(left_markers_arg())
+                .arg(right_markers_arg())
+                .arg(
+                    Arg::new("SACRUM")
+                        .long("sacrum")
+                        .default_value("SACR,LPSI,RPSI")
+                        .help("The comma separated markers averaged into the sacrum for marker based detection"),
+                )
+                .arg(
+                    Arg::new("UP")
+                        .long("up")
+                        .default_value("z")
+                        .help("The vertical lab axis for marker based detection: x, y or z"),
+                )
+                .arg(
+                    Arg::new("REPLACE")
+                        .long("replace")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove the existing foot strike and foot off events first"),
+                ),
+        )
+}
+
+fn marker_list(sub_matches: &ArgMatches, name: &str) -> Vec<String> {
+    sub_matches
+        .get_one::<String>(name)
+        .map(|markers| markers.split(',').map(|m| m.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Reads the `--assign`, `--left-markers` and `--right-markers` options.
+pub(crate) fn foot_assignment(sub_matches: &ArgMatches) -> FootAssignment {
+    match sub_matches.get_one::<String>("ASSIGN").map(|assign| assign.as_str()) {
+        Some("events") => FootAssignment::Events,
+        _ => FootAssignment::Markers(FootMarkers {
+            left: marker_list(sub_matches, "LEFT_MARKERS"),
+            right: marker_list(sub_matches, "RIGHT_MARKERS"),
+        }),
+    }
+}
+
+fn label_arg() -> Arg {
+    Arg::new("LABEL")
+        .short('l')
+        .long("label")
+        .help("The event label, for example \"Foot Strike\"")
+}
+
+fn context_arg() -> Arg {
+    Arg::new("CONTEXT")
+        .short('c')
+        .long("context")
+        .help("The event context, for example Left, Right or General")
+}
+
+fn time_arg() -> Arg {
+    Arg::new("TIME")
+        .short('t')
+        .long("time")
+        .value_parser(clap::value_parser!(f32))
+        .help("The event time in seconds")
+}
+
+fn frame_arg() -> Arg {
+    Arg::new("FRAME")
+        .long("frame")
+        .value_parser(clap::value_parser!(usize))
+        .help("The event frame number")
+}
+
+pub(super) fn process_events_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let (name, sub_matches) = match sub_matches.subcommand() {
+        Some(subcommand) => subcommand,
+        None => return Ok(()),
+    };
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    if !(name == "list" && sub_matches.get_flag("JSON")) {
+        println!("Opening {}", file.green());
+    }
+    let mut c3d = C3d::load(file).map_err(CliError::load)?;
+    let edited = match name {
+        "list" => {
+            if sub_matches.get_flag("JSON") {
+                let document = serde_json::to_string_pretty(&events_json(&c3d))
+                    .map_err(|e| CliError::Processing(e.to_string()))?;
+                println!("{}", document);
+            } else {
+                print!("{}", events_table(&c3d));
+            }
+            return Ok(());
+        }
+        "add" => {
+            let label = sub_matches.get_one::<String>("LABEL").unwrap();
+            let context = sub_matches.get_one::<String>("CONTEXT").unwrap();
+            let time = match (
+                sub_matches.get_one::<f32>("TIME"),
+                sub_matches.get_one::<usize>("FRAME"),
+            ) {
+                (Some(time), _) => *time,
+                (_, Some(frame)) => frame_time(&c3d, *frame),
+                _ => {
+                    return Err(CliError::Arguments(
+                        "Either --time or --frame is required".to_string(),
+                    ))
+                }
+            };
+            add_event(&mut c3d, context, label, time)
+                .map(|_| println!("Added {} {} at {:.3}s", context, label, time))
+                .map_err(CliError::Processing)
+        }
+        "remove" => {
+            let label = sub_matches.get_one::<String>("LABEL");
+            let context = sub_matches.get_one::<String>("CONTEXT");
+            let frame = match (
+                sub_matches.get_one::<f32>("TIME"),
+                sub_matches.get_one::<usize>("FRAME"),
+            ) {
+                (Some(time), _) => Some(time_frame(&c3d, *time)),
+                (_, Some(frame)) => Some(*frame),
+                _ => None,
+            };
+            let before = c3d.events.len();
+            let matches = |event: &Event| {
+                label.map_or(true, |l| event.label.trim().eq_ignore_ascii_case(l.trim()))
+                    && context.map_or(true, |c| {
+                        event.context.trim().eq_ignore_ascii_case(c.trim())
+                    })
+                    && frame.map_or(true, |f| time_frame(&c3d, event.time) == f)
+            };
+            let kept: Vec<Event> = c3d.events.iter().filter(|e| !matches(e)).cloned().collect();
+            *c3d.events = kept;
+            println!("Removed {} events", before - c3d.events.len());
+            Ok(())
+        }
+        "import" => {
+            let csv = sub_matches.get_one::<String>("CSV").unwrap();
+            println!("Opening {}", csv.green());
+            if sub_matches.get_flag("REPLACE") {
+                c3d.events.clear();
+            }
+            import_events(&mut c3d, Path::new(csv))
+                .map(|count| println!("Imported {} events", count))
+                .map_err(CliError::Load)
+        }
+        "detect" => {
+            let markers = FootMarkers {
+                left: marker_list(sub_matches, "LEFT_MARKERS"),
+                right: marker_list(sub_matches, "RIGHT_MARKERS"),
+            };
+            let method = sub_matches.get_one::<String>("METHOD").unwrap();
+            let events = match method.trim().to_lowercase().as_str() {
+                "force" => {
+                    let options = ForceDetection {
+                        threshold: *sub_matches.get_one::<f64>("THRESHOLD").unwrap(),
+                        hysteresis: *sub_matches.get_one::<f64>("HYSTERESIS").unwrap(),
+                        markers,
+                    };
+                    options
+                        .check()
+                        .map(|_| detect_force_events(&c3d, &options))
+                }
+                "coordinate" | "velocity" => {
+                    let up = sub_matches.get_one::<String>("UP").unwrap();
+                    let up = match up.trim().to_lowercase().as_str() {
+                        "x" => 0,
+                        "y" => 1,
+                        "z" => 2,
+                        _ => {
+                            return Err(CliError::Arguments(format!(
+                                "{} is not a valid axis, axes allowed: x, y, z",
+                                up
+                            )))
+                        }
+                    };
+                    let options = KinematicDetection {
+                        method: match method.trim().to_lowercase().as_str() {
+                            "velocity" => KinematicMethod::Velocity,
+                            _ => KinematicMethod::Coordinate,
+                        },
+                        markers,
+                        sacrum: marker_list(sub_matches, "SACRUM"),
+                        up,
+                    };
+                    detect_kinematic_events(&c3d, &options)
+                }
+                _ => Err(format!(
+                    "{} is not a valid detection method, methods allowed: force, coordinate, velocity",
+                    method
+                )),
+            };
+            // detection fails on the markers or method the user asked for
+            let events = events.map_err(CliError::Arguments)?;
+            for event in &events {
+                println!(
+                    "Detected {} {} at {:.3}s",
+                    event.context, event.label, event.time
+                );
+            }
+            let dropped =
+                write_gait_events(&mut c3d, &events, sub_matches.get_flag("REPLACE"));
+            if dropped > 0 {
+                println!(
+                    "{}",
+                    format!(
+                        "The header holds {} events, the last {} were left out",
+                        MAX_HEADER_EVENTS, dropped
+                    )
+                    .yellow()
+                );
+            }
+            Ok(())
+        }
+        _ => return Ok(()),
+    };
+    edited?;
+    let output: PathBuf = match sub_matches.get_one::<String>("OUTPUT") {
+        Some(output) => output.into(),
+        None => file.into(),
+    };
+    c3d.write_path(output.clone()).map_err(CliError::write)?;
+    println!("Wrote {}", output.to_string_lossy().green());
+    Ok(())
+}
+
+/// Adds an event and keeps the events sorted by time.
+/// Fails if the header has no room for another event.
+pub(crate) fn add_event(
+    c3d: &mut C3d,
+    context: &str,
+    label: &str,
+    time: f32,
+) -> Result<(), String> {
+    if c3d.events.len() >= MAX_HEADER_EVENTS {
+        return Err(format!(
+            "The file already has {} events, the most a C3D header can hold",
+            MAX_HEADER_EVENTS
+        ));
+    }
+    let event = new_event(c3d, context, label, time);
+    let index = c3d
+        .events
+        .iter()
+        .position(|e| e.time > time)
+        .unwrap_or(c3d.events.len());
+    c3d.events.insert(index, event);
+    c3d.events.supports_events_labels = true;
+    Ok(())
+}
+
+/// Builds an event with the id, subject and icon other software expects for its label.
+pub(crate) fn new_event(c3d: &C3d, context: &str, label: &str, time: f32) -> Event {
+    let mut id = [' '; 4];
+    for (i, c) in label
+        .split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .take(4)
+        .enumerate()
+    {
+        id[i] = c.to_ascii_uppercase();
+    }
+    // reuse the subject of existing events so Nexus keeps them with the same subject
+    let subject = c3d
+        .events
+        .first()
+        .map(|event| event.subject.clone())
+        .unwrap_or_default();
+    Event {
+        id,
+        label: label.to_string(),
+        display_flag: true,
+        time,
+        context: context.to_string(),
+        subject,
+        icon_id: match label.trim().to_lowercase().as_str() {
+            "foot strike" => 1,
+            "foot off" => 2,
+            _ => 0,
+        },
+        ..Default::default()
+    }
+}
+
+/// Reads events from CSV rows of context,label,time. A header row naming the third
+/// column `frame` switches it to frame numbers.
+fn import_events(c3d: &mut C3d, path: &Path) -> Result<usize, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut frames = false;
+    let mut count = 0;
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() < 3 {
+            return Err(format!(
+                "Line {} of {} is not a context,label,time row",
+                i + 1,
+                path.to_string_lossy()
+            ));
+        }
+        let time = match fields[2].parse::<f32>() {
+            Ok(value) if frames => frame_time(c3d, value.round() as usize),
+            Ok(value) => value,
+            Err(_) if count == 0 => {
+                frames = fields[2].eq_ignore_ascii_case("frame");
+                continue;
+            }
+            Err(_) => {
+                return Err(format!(
+                    "Line {} of {} has an invalid time {}",
+                    i + 1,
+                    path.to_string_lossy(),
+                    fields[2]
+                ))
+            }
+        };
+        add_event(c3d, fields[0], fields[1], time)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn events_table(c3d: &C3d) -> String {
+    let mut table = format!(
+        "{:<10}  {:<20}  {:>10}  {:>8}\n",
+        "Context", "Label", "Time", "Frame"
+    );
+    for event in c3d.events.iter() {
+        table.push_str(&format!(
+            "{:<10}  {:<20}  {:>10.4}  {:>8}\n",
+            event.context.trim(),
+            event.label.trim(),
+            event.time,
+            time_frame(c3d, event.time)
+        ));
+    }
+    table
+}
+
+fn events_json(c3d: &C3d) -> Value {
+    let events: Vec<Value> = c3d
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "context": event.context.trim(),
+                "label": event.label.trim(),
+                "time": event.time,
+                "frame": time_frame(c3d, event.time),
+                "description": event.description.trim(),
+                "subject": event.subject.trim(),
+            })
+        })
+        .collect();
+    Value::Array(events)
+}
@@ -0,0 +1,119 @@
+//! CSV files store marker trajectories as a plain table with one header row.
+//! They can be read directly by pandas, R or a spreadsheet.
+use c3dio::prelude::*;
+use grid::Grid;
+use std::fmt::Display;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::gaps::is_gap;
+
+/// The value written in place of a marker coordinate when the marker is missing.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub(crate) enum GapValue {
+    #[default]
+    Empty,
+    Nan,
+    Zero,
+}
+
+impl FromStr for GapValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "empty" => Ok(GapValue::Empty),
+            "nan" => Ok(GapValue::Nan),
+            "zero" | "0" => Ok(GapValue::Zero),
+            _ => Err(format!(
+                "{} is not a valid gap value, values allowed: empty, nan, zero",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for GapValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GapValue::Empty => write!(f, ""),
+            GapValue::Nan => write!(f, "NaN"),
+            GapValue::Zero => write!(f, "0"),
+        }
+    }
+}
+
+/// The CSV struct contains the data for writing a CSV file.
+#[derive(Debug, Clone)]
+pub(crate) struct Csv {
+    pub data_rate: f32,
+    pub first_frame: usize,
+    pub marker_names: Vec<String>,
+    pub delimiter: char,
+    pub gap_value: GapValue,
+    pub data: Grid<MarkerPoint>,
+}
+
+impl Csv {
+    pub fn from_c3d(c3d: &C3d) -> Self {
+        let mut marker_names = c3d.points.labels.clone();
+        if marker_names.len() > c3d.points.size().1 {
+            marker_names = marker_names[0..c3d.points.size().1].to_vec();
+        }
+        Csv {
+            data_rate: c3d.points.frame_rate,
+            first_frame: c3d.points.first_frame as usize,
+            marker_names,
+            delimiter: ',',
+            gap_value: GapValue::default(),
+            data: c3d.points.points.clone(),
+        }
+    }
+
+    pub fn write(&self, file_name: PathBuf) -> Result<(), C3dWriteError> {
+        if file_name.is_dir() {
+            return Err(C3dWriteError::InvalidFilePath(file_name));
+        }
+        let file = std::fs::File::create(file_name.clone())
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        let mut file = BufWriter::new(file);
+        let delimiter = self.delimiter.to_string();
+
+        let mut header = vec!["Frame".to_string(), "Time".to_string()];
+        for i in 0..self.data.size().1 {
+            let name = match self.marker_names.get(i) {
+                Some(name) => name.trim().to_string(),
+                None => format!("{}", i + 1),
+            };
+            header.push(format!("{}_X", name));
+            header.push(format!("{}_Y", name));
+            header.push(format!("{}_Z", name));
+        }
+        writeln!(file, "{}", header.join(&delimiter))
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+
+        for i in 0..self.data.size().0 {
+            let mut line = vec![
+                format!("{}", i + self.first_frame),
+                format!("{}", (i + self.first_frame) as f32 / self.data_rate),
+            ];
+            for j in 0..self.data.size().1 {
+                let point = &self.data[(i, j)];
+                if is_gap(point) {
+                    let gap = self.gap_value.to_string();
+                    line.extend([gap.clone(), gap.clone(), gap]);
+                } else {
+                    line.push(format!("{}", point[0]));
+                    line.push(format!("{}", point[1]));
+                    line.push(format!("{}", point[2]));
+                }
+            }
+            writeln!(file, "{}", line.join(&delimiter))
+                .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        }
+        file.flush()
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+//! Structures for auxiliary file formats that are not provided by c3dio.
+
+pub(super) mod csv;
+pub(super) mod qualisys_tsv;
+pub(super) mod sto;
+pub(super) mod table;
+pub(super) mod trc;
+pub(super) mod vicon_csv;
+
+use c3dio::prelude::*;
+
+/// A marker sample read from a file without residuals. It gets a positive residual,
+/// since a residual of 0 marks a sample as interpolated.
+pub(crate) fn measured_point(point: [f32; 3]) -> MarkerPoint {
+    MarkerPoint {
+        point,
+        residual: 1.,
+        cameras: [false; 7],
+    }
+}
@@ -0,0 +1,132 @@
+//! Qualisys Track Manager exports 3D marker data as TSV files with a header of
+//! named values, such as FREQUENCY and MARKER_NAMES, followed by the data.
+use c3dio::prelude::*;
+use grid::Grid;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::measured_point;
+
+/// The header and marker data of a Qualisys TSV export. QTM always exports millimeters.
+#[derive(Debug, Clone)]
+pub(crate) struct QualisysTsvFile {
+    pub data_rate: f32,
+    pub first_frame: usize,
+    pub marker_names: Vec<String>,
+    pub data: Grid<MarkerPoint>,
+}
+
+impl QualisysTsvFile {
+    /// Reads a Qualisys 3D TSV export. Blank coordinates, and markers at exactly
+    /// the origin as QTM writes missing markers, become gaps.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+        // header lines start with an upper case name and hold tab separated values
+        let mut header = HashMap::new();
+        while let Some(line) = lines.next_if(|line| is_header(line)) {
+            let mut fields = line.split('\t').map(|field| field.trim());
+            let name = fields.next().unwrap_or_default().to_string();
+            header.insert(
+                name,
+                fields.filter(|field| !field.is_empty()).collect::<Vec<_>>(),
+            );
+        }
+        if !header.contains_key("MARKER_NAMES") {
+            return Err(format!(
+                "{} is not a Qualisys 3D export, it has no MARKER_NAMES",
+                path.to_string_lossy()
+            ));
+        }
+        let data_rate = header
+            .get("FREQUENCY")
+            .and_then(|values| values.first())
+            .and_then(|rate| rate.parse::<f32>().ok())
+            .filter(|rate| *rate > 0.)
+            .ok_or("The TSV header has no valid FREQUENCY")?;
+        let mut marker_names = header["MARKER_NAMES"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let num_markers = header
+            .get("NO_OF_MARKERS")
+            .and_then(|values| values.first())
+            .and_then(|markers| markers.parse::<usize>().ok())
+            .unwrap_or(marker_names.len())
+            .max(marker_names.len());
+        while marker_names.len() < num_markers {
+            marker_names.push(format!("M{}", marker_names.len() + 1));
+        }
+        // newer versions name the columns and lead each row with the frame and time
+        let labelled = lines
+            .next_if(|line| line.trim_start().starts_with("Frame"))
+            .is_some();
+        let mut data = Grid::new(0, num_markers);
+        let mut first_frame = None;
+        for (row, line) in lines.enumerate() {
+            let fields = line
+                .split('\t')
+                .map(|field| field.trim())
+                .collect::<Vec<_>>();
+            let offset = match labelled || fields.len() >= num_markers * 3 + 2 {
+                true => 2,
+                false => 0,
+            };
+            if first_frame.is_none() && offset == 2 {
+                first_frame = fields
+                    .first()
+                    .and_then(|frame| frame.parse::<usize>().ok())
+                    .map(|frame| frame.max(1));
+            }
+            let mut points = Vec::with_capacity(num_markers);
+            for marker in 0..num_markers {
+                let mut point = [0f32; 3];
+                let mut missing = false;
+                for (axis, value) in point.iter_mut().enumerate() {
+                    match fields.get(offset + marker * 3 + axis) {
+                        Some(field) if !field.is_empty() => match field.parse::<f32>() {
+                            Ok(parsed) if parsed.is_finite() => *value = parsed,
+                            Ok(_) => missing = true,
+                            Err(_) => {
+                                return Err(format!(
+                                    "{} on data row {} is not a number",
+                                    field,
+                                    row + 1
+                                ))
+                            }
+                        },
+                        _ => missing = true,
+                    }
+                }
+                points.push(match missing || point == [0.; 3] {
+                    true => MarkerPoint {
+                        point: [0.; 3],
+                        residual: -1.,
+                        cameras: [false; 7],
+                    },
+                    false => measured_point(point),
+                });
+            }
+            if num_markers > 0 {
+                data.push_row(points);
+            }
+        }
+        Ok(QualisysTsvFile {
+            data_rate,
+            first_frame: first_frame.unwrap_or(1),
+            marker_names,
+            data,
+        })
+    }
+}
+
+fn is_header(line: &str) -> bool {
+    let name = line.split('\t').next().unwrap_or_default().trim();
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
@@ -0,0 +1,131 @@
+//! STO and MOT storage files hold OpenSim time series such as simulated ground
+//! reactions, muscle excitations or joint angles. c3dio only writes them, so
+//! this reads them back for merging into C3D files.
+use grid::Grid;
+use std::path::Path;
+
+/// The columns of a storage file, without its time column.
+#[derive(Debug, Clone)]
+pub(crate) struct StoFile {
+    pub column_names: Vec<String>,
+    pub times: Vec<f64>,
+    pub data: Grid<f64>,
+}
+
+impl StoFile {
+    /// Reads a storage file with a header ending in `endheader` and a leading time column.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = contents.lines();
+        loop {
+            let Some(line) = lines.next() else {
+                return Err(format!(
+                    "{} is not a storage file, it has no endheader line",
+                    path.to_string_lossy()
+                ));
+            };
+            if line.trim().eq_ignore_ascii_case("endheader") {
+                break;
+            }
+        }
+        let mut lines = lines.filter(|line| !line.trim().is_empty());
+        let column_names = split_fields(lines.next().unwrap_or_default());
+        match column_names.first() {
+            Some(time) if time.eq_ignore_ascii_case("time") => {}
+            _ => return Err("The first column of a storage file must be time".to_string()),
+        }
+        let column_names = column_names[1..]
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let mut times = Vec::new();
+        let mut data = Grid::new(0, column_names.len());
+        for (row, line) in lines.enumerate() {
+            let values = split_fields(line)
+                .iter()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    format!(
+                        "Data row {} has a value that is not a number: {}",
+                        row + 1,
+                        e
+                    )
+                })?;
+            if values.len() != column_names.len() + 1 {
+                return Err(format!(
+                    "Data row {} has {} values but there are {} columns",
+                    row + 1,
+                    values.len(),
+                    column_names.len() + 1
+                ));
+            }
+            times.push(values[0]);
+            if !column_names.is_empty() {
+                data.push_row(values[1..].to_vec());
+            }
+        }
+        if times.windows(2).any(|pair| pair[1] <= pair[0]) {
+            return Err("The times of a storage file must increase".to_string());
+        }
+        Ok(StoFile {
+            column_names,
+            times,
+            data,
+        })
+    }
+
+    /// Returns the index of a column by name, ignoring case.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.column_names
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+    }
+
+    /// Linearly interpolates a column at `time`, or returns `None` outside the times of the file.
+    pub fn value_at(&self, column: usize, time: f64) -> Option<f64> {
+        let (first, last) = (*self.times.first()?, *self.times.last()?);
+        // allow for rounding in the times written to the file
+        let tolerance = 1e-6 * last.abs().max(1.);
+        if time < first - tolerance || time > last + tolerance {
+            return None;
+        }
+        let after = self.times.partition_point(|t| *t < time);
+        // times written with less precision still give back the sample itself
+        let nearest = [after.saturating_sub(1), after.min(self.times.len() - 1)]
+            .into_iter()
+            .min_by(|a, b| {
+                (self.times[*a] - time)
+                    .abs()
+                    .total_cmp(&(self.times[*b] - time).abs())
+            })?;
+        if (self.times[nearest] - time).abs() <= tolerance {
+            return Some(self.data[(nearest, column)]);
+        }
+        if after == 0 {
+            return Some(self.data[(0, column)]);
+        }
+        if after == self.times.len() {
+            return Some(self.data[(after - 1, column)]);
+        }
+        let (t0, t1) = (self.times[after - 1], self.times[after]);
+        let (v0, v1) = (self.data[(after - 1, column)], self.data[(after, column)]);
+        Some(v0 + (v1 - v0) * (time - t0) / (t1 - t0))
+    }
+}
+
+/// Splits a line on tabs, or on whitespace if it has none, dropping the trailing
+/// empty field left by writers that end every value with a tab.
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut fields = match line.contains('\t') {
+        true => line
+            .split('\t')
+            .map(|field| field.trim())
+            .collect::<Vec<_>>(),
+        false => line.split_whitespace().collect(),
+    };
+    while fields.last().is_some_and(|field| field.is_empty()) {
+        fields.pop();
+    }
+    fields
+}
@@ -0,0 +1,107 @@
+//! Tables store time series such as analog channels with a leading time column.
+//! They are written as CSV, or as the STO and MOT storage files read by OpenSim.
+use c3dio::prelude::*;
+use grid::Grid;
+use std::fmt::Display;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum TableFormat {
+    Csv,
+    Sto,
+    Mot,
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(TableFormat::Csv),
+            "sto" => Ok(TableFormat::Sto),
+            "mot" => Ok(TableFormat::Mot),
+            _ => Err(format!(
+                "{} is not a valid output file type, types allowed: .csv, .sto, .mot",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for TableFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableFormat::Csv => write!(f, "csv"),
+            TableFormat::Sto => write!(f, "sto"),
+            TableFormat::Mot => write!(f, "mot"),
+        }
+    }
+}
+
+/// The Table struct contains the data for writing a time series file.
+#[derive(Debug, Clone)]
+pub(crate) struct Table {
+    /// The time in seconds of the first row.
+    pub start_time: f64,
+    pub data_rate: f64,
+    pub column_names: Vec<String>,
+    pub data: Grid<f64>,
+}
+
+impl Table {
+    pub fn time(&self, row: usize) -> f64 {
+        self.start_time + row as f64 / self.data_rate
+    }
+
+    pub fn write(&self, file_name: PathBuf, format: TableFormat) -> Result<(), C3dWriteError> {
+        if file_name.is_dir() {
+            return Err(C3dWriteError::InvalidFilePath(file_name));
+        }
+        let file = std::fs::File::create(file_name.clone())
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        let mut file = BufWriter::new(file);
+        let delimiter = match format {
+            TableFormat::Csv => ",",
+            TableFormat::Sto | TableFormat::Mot => "\t",
+        };
+
+        if format != TableFormat::Csv {
+            let name = file_name
+                .file_stem()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            // nColumns counts the time column in OpenSim storage files
+            write!(
+                file,
+                "{}\nversion=1\nnRows={}\nnColumns={}\ninDegrees=no\nendheader\n",
+                name,
+                self.data.size().0,
+                self.data.size().1 + 1
+            )
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        }
+        let mut header = vec!["time".to_string()];
+        for i in 0..self.data.size().1 {
+            header.push(match self.column_names.get(i) {
+                Some(name) => name.trim().to_string(),
+                None => format!("Column_{}", i + 1),
+            });
+        }
+        writeln!(file, "{}", header.join(delimiter))
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+
+        for row in 0..self.data.size().0 {
+            let mut line = vec![format!("{}", self.time(row))];
+            for column in 0..self.data.size().1 {
+                line.push(format!("{}", self.data[(row, column)]));
+            }
+            writeln!(file, "{}", line.join(delimiter))
+                .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        }
+        file.flush()
+            .map_err(|e| C3dWriteError::WriteError(file_name.clone(), e))?;
+        Ok(())
+    }
+}
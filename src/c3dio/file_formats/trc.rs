@@ -0,0 +1,135 @@
+//! TRC files store marker trajectories for OpenSim and Motion Analysis software.
+//! c3dio only writes them, so this reads them back for importing.
+use c3dio::prelude::*;
+use grid::Grid;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::measured_point;
+
+/// The header and marker data of a TRC file.
+#[derive(Debug, Clone)]
+pub(crate) struct TrcFile {
+    pub data_rate: f32,
+    pub units: String,
+    pub first_frame: usize,
+    pub marker_names: Vec<String>,
+    pub data: Grid<MarkerPoint>,
+}
+
+impl TrcFile {
+    /// Reads a tab separated TRC file. Blank or NaN coordinates become gaps.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = contents.lines();
+        match lines.next() {
+            Some(line) if line.trim_start().starts_with("PathFileType") => {}
+            _ => {
+                return Err(format!(
+                    "{} is not a TRC file, it does not start with PathFileType",
+                    path.to_string_lossy()
+                ))
+            }
+        }
+        // the second line names the values on the third
+        let names = lines.next().unwrap_or_default().split('\t');
+        let values = lines.next().unwrap_or_default().split('\t');
+        let header = names
+            .map(|name| name.trim().to_lowercase())
+            .zip(values.map(|value| value.trim().to_string()))
+            .collect::<HashMap<_, _>>();
+        let data_rate = header
+            .get("datarate")
+            .and_then(|rate| rate.parse::<f32>().ok())
+            .filter(|rate| *rate > 0.)
+            .ok_or("The TRC header has no valid DataRate")?;
+        let units = header
+            .get("units")
+            .filter(|units| !units.is_empty())
+            .cloned()
+            .unwrap_or("mm".to_string());
+        // marker names sit above the X column of each marker, every third column
+        let mut marker_names = lines
+            .next()
+            .unwrap_or_default()
+            .split('\t')
+            .skip(2)
+            .step_by(3)
+            .map(|name| name.trim().to_string())
+            .collect::<Vec<_>>();
+        while marker_names.last().is_some_and(|name| name.is_empty()) {
+            marker_names.pop();
+        }
+        let num_markers = header
+            .get("nummarkers")
+            .and_then(|markers| markers.parse::<usize>().ok())
+            .unwrap_or(marker_names.len())
+            .max(marker_names.len());
+        for (i, name) in marker_names.iter_mut().enumerate() {
+            if name.is_empty() {
+                *name = format!("M{}", i + 1);
+            }
+        }
+        while marker_names.len() < num_markers {
+            marker_names.push(format!("M{}", marker_names.len() + 1));
+        }
+        // skip the X1 Y1 Z1 line, the data follows after an optional blank line
+        let rows = lines
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        let mut data = Grid::new(0, num_markers);
+        let mut first_frame = None;
+        for (row, line) in rows.iter().enumerate() {
+            let fields = line
+                .split('\t')
+                .map(|field| field.trim())
+                .collect::<Vec<_>>();
+            if first_frame.is_none() {
+                first_frame = fields
+                    .first()
+                    .and_then(|frame| frame.parse::<f64>().ok())
+                    .map(|frame| frame.max(1.) as usize);
+            }
+            let mut points = Vec::with_capacity(num_markers);
+            for marker in 0..num_markers {
+                let mut point = [0f32; 3];
+                let mut missing = false;
+                for (axis, value) in point.iter_mut().enumerate() {
+                    match fields.get(2 + marker * 3 + axis) {
+                        Some(field) if !field.is_empty() => match field.parse::<f32>() {
+                            Ok(parsed) if parsed.is_finite() => *value = parsed,
+                            Ok(_) => missing = true,
+                            Err(_) => {
+                                return Err(format!(
+                                    "{} on data row {} is not a number",
+                                    field,
+                                    row + 1
+                                ))
+                            }
+                        },
+                        _ => missing = true,
+                    }
+                }
+                points.push(match missing {
+                    true => MarkerPoint {
+                        point: [0.; 3],
+                        residual: -1.,
+                        cameras: [false; 7],
+                    },
+                    false => measured_point(point),
+                });
+            }
+            if num_markers > 0 {
+                data.push_row(points);
+            }
+        }
+        Ok(TrcFile {
+            data_rate,
+            units,
+            first_frame: first_frame.unwrap_or(1),
+            marker_names,
+            data,
+        })
+    }
+}
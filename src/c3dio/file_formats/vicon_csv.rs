@@ -0,0 +1,127 @@
+//! Vicon Nexus exports marker trajectories as CSV files split into sections such
+//! as Devices, Trajectories and Model Outputs. Only the Trajectories section is
+//! read, the other sections are skipped.
+use c3dio::prelude::*;
+use grid::Grid;
+use std::path::Path;
+
+use super::measured_point;
+
+/// The trajectories of a Vicon Nexus CSV export.
+#[derive(Debug, Clone)]
+pub(crate) struct ViconCsvFile {
+    pub data_rate: f32,
+    pub units: String,
+    pub first_frame: usize,
+    pub marker_names: Vec<String>,
+    pub data: Grid<MarkerPoint>,
+}
+
+impl ViconCsvFile {
+    /// Reads the Trajectories section of a Vicon Nexus CSV export. Blank coordinates become gaps.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = contents
+            .lines()
+            .skip_while(|line| !first_field(line).eq_ignore_ascii_case("trajectories"));
+        if lines.next().is_none() {
+            return Err(format!(
+                "{} is not a Vicon CSV export, it has no Trajectories section",
+                path.to_string_lossy()
+            ));
+        }
+        let data_rate = lines
+            .next()
+            .map(first_field)
+            .and_then(|rate| rate.parse::<f32>().ok())
+            .filter(|rate| *rate > 0.)
+            .ok_or("The Trajectories section has no valid frame rate")?;
+        // marker names sit above the X column of each marker, prefixed by the subject
+        let mut marker_names = split_fields(lines.next().unwrap_or_default())
+            .into_iter()
+            .skip(2)
+            .step_by(3)
+            .map(|name| match name.rsplit_once(':') {
+                Some((_, marker)) => marker.to_string(),
+                None => name.to_string(),
+            })
+            .collect::<Vec<_>>();
+        while marker_names.last().is_some_and(|name| name.is_empty()) {
+            marker_names.pop();
+        }
+        for (i, name) in marker_names.iter_mut().enumerate() {
+            if name.is_empty() {
+                *name = format!("M{}", i + 1);
+            }
+        }
+        let num_markers = marker_names.len();
+        // skip the Frame, Sub Frame, X, Y, Z line, the units line follows
+        let units = split_fields(lines.nth(1).unwrap_or_default())
+            .get(2)
+            .filter(|units| !units.is_empty())
+            .map(|units| units.to_string())
+            .unwrap_or("mm".to_string());
+        let mut data = Grid::new(0, num_markers);
+        let mut first_frame = None;
+        // the section ends at the first blank line
+        for (row, line) in lines
+            .take_while(|line| !line.trim().trim_matches(',').is_empty())
+            .enumerate()
+        {
+            let fields = split_fields(line);
+            if first_frame.is_none() {
+                first_frame = fields
+                    .first()
+                    .and_then(|frame| frame.parse::<usize>().ok())
+                    .map(|frame| frame.max(1));
+            }
+            let mut points = Vec::with_capacity(num_markers);
+            for marker in 0..num_markers {
+                let mut point = [0f32; 3];
+                let mut missing = false;
+                for (axis, value) in point.iter_mut().enumerate() {
+                    match fields.get(2 + marker * 3 + axis) {
+                        Some(field) if !field.is_empty() => match field.parse::<f32>() {
+                            Ok(parsed) if parsed.is_finite() => *value = parsed,
+                            Ok(_) => missing = true,
+                            Err(_) => {
+                                return Err(format!(
+                                    "{} on data row {} is not a number",
+                                    field,
+                                    row + 1
+                                ))
+                            }
+                        },
+                        _ => missing = true,
+                    }
+                }
+                points.push(match missing {
+                    true => MarkerPoint {
+                        point: [0.; 3],
+                        residual: -1.,
+                        cameras: [false; 7],
+                    },
+                    false => measured_point(point),
+                });
+            }
+            if num_markers > 0 {
+                data.push_row(points);
+            }
+        }
+        Ok(ViconCsvFile {
+            data_rate,
+            units,
+            first_frame: first_frame.unwrap_or(1),
+            marker_names,
+            data,
+        })
+    }
+}
+
+fn split_fields(line: &str) -> Vec<&str> {
+    line.split(',').map(|field| field.trim()).collect()
+}
+
+fn first_field(line: &str) -> &str {
+    line.split(',').next().unwrap_or_default().trim()
+}
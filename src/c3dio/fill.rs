@@ -0,0 +1,342 @@
+use c3dio::prelude::*;
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::args::{file_arg, jobs_arg, output_arg};
+use crate::batch::{check_output, find_files, finish_batch, job_count, run_batch, suffixed_name};
+use crate::error::CliError;
+use crate::gaps::{find_gaps, GapKind};
+
+/// The number of valid frames on each side of a gap used to fit a spline.
+const SPLINE_CONTEXT: usize = 10;
+
+pub(super) fn fill_command() -> Command {
+    Command::new("fill")
+        .about("Fills marker gaps in C3D files and writes new C3D files")
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            Arg::new("METHOD")
+                .short('m')
+                .long("method")
+                .default_value("linear")
+                .help("The interpolation method: linear, spline or pattern"),
+        )
+        .arg(
+            Arg::new("MAX_GAP")
+                .short('g')
+                .long("max-gap")
+                .default_value("10")
+                .value_parser(clap::value_parser!(usize))
+                .help("The longest gap in frames that will be filled"),
+        )
+        .arg(
+            Arg::new("DONOR")
+                .long("donor")
+                .help("The marker whose trajectory is followed by pattern interpolation"),
+        )
+        .arg(
+            Arg::new("MARKERS")
+                .long("markers")
+                .help("A comma separated list of markers to fill, all markers by default"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_fill_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let method = sub_matches.get_one::<String>("METHOD").unwrap();
+    let method = FillMethod::from_str(method).map_err(CliError::Arguments)?;
+    let max_gap = *sub_matches.get_one::<usize>("MAX_GAP").unwrap();
+    let donor = sub_matches.get_one::<String>("DONOR");
+    if method == FillMethod::Pattern && donor.is_none() {
+        return Err(CliError::Arguments("Pattern interpolation requires a --donor marker".to_string()));
+    }
+    let markers: Option<Vec<String>> = sub_matches
+        .get_one::<String>("MARKERS")
+        .map(|markers| markers.split(',').map(|m| m.trim().to_string()).collect());
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let (output, suffix): (PathBuf, &str) = match output {
+        Some(output) => (output.into(), ""),
+        None => {
+            // set output to current directory, named so the inputs are not replaced
+            println!(
+                "{}",
+                "No output file was provided, writing _filled files to current directory".yellow()
+            );
+            (std::env::current_dir().map_err(CliError::write)?, "_filled")
+        }
+    };
+    let files = find_files(file)?;
+    let options = FillOptions {
+        method,
+        max_gap,
+        donor: donor.cloned(),
+        markers,
+    };
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        for summary in fill_markers(&mut c3d, &options).map_err(CliError::Arguments)? {
+            progress.status(summary.to_string());
+        }
+        let output = match output.is_dir() {
+            true => output.join(suffixed_name(file, suffix)),
+            false => output.clone(),
+        };
+        check_output(file, &output)?;
+        c3d.write_path(output.clone()).map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum FillMethod {
+    Linear,
+    Spline,
+    Pattern,
+}
+
+impl FromStr for FillMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "linear" => Ok(FillMethod::Linear),
+            "spline" | "cubic" => Ok(FillMethod::Spline),
+            "pattern" => Ok(FillMethod::Pattern),
+            _ => Err(format!(
+                "{} is not a valid fill method, methods allowed: linear, spline, pattern",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for FillMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FillMethod::Linear => write!(f, "linear"),
+            FillMethod::Spline => write!(f, "spline"),
+            FillMethod::Pattern => write!(f, "pattern"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FillOptions {
+    pub method: FillMethod,
+    pub max_gap: usize,
+    pub donor: Option<String>,
+    pub markers: Option<Vec<String>>,
+}
+
+/// What was filled in a single marker.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FillSummary {
+    pub label: String,
+    pub filled_gaps: usize,
+    pub filled_frames: usize,
+    pub skipped_gaps: usize,
+}
+
+impl Display for FillSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: filled {} gaps ({} frames), left {} gaps unfilled",
+            self.label, self.filled_gaps, self.filled_frames, self.skipped_gaps
+        )
+    }
+}
+
+/// Fills the gaps of the selected markers and returns a summary for every marker with gaps.
+pub(crate) fn fill_markers(
+    c3d: &mut C3d,
+    options: &FillOptions,
+) -> Result<Vec<FillSummary>, String> {
+    let donor = match (&options.method, &options.donor) {
+        (FillMethod::Pattern, Some(donor)) => Some(marker_column(c3d, donor)?),
+        (FillMethod::Pattern, None) => {
+            return Err("Pattern interpolation requires a donor marker".to_string())
+        }
+        _ => None,
+    };
+    let columns = match &options.markers {
+        Some(markers) => markers
+            .iter()
+            .map(|marker| marker_column(c3d, marker))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => (0..c3d.points.cols()).collect(),
+    };
+    let mut summaries = Vec::new();
+    for column in columns {
+        if Some(column) == donor {
+            continue;
+        }
+        let mut summary = FillSummary {
+            label: c3d.points.labels.get(column).cloned().unwrap_or_default(),
+            ..Default::default()
+        };
+        let gaps = find_gaps(c3d, column);
+        for gap in gaps.iter().filter(|gap| gap.kind == GapKind::Invalid) {
+            let before = gap.start.checked_sub(1);
+            let after = gap.end() + 1;
+            let filled = match before {
+                Some(before) if gap.length <= options.max_gap && after < c3d.points.rows() => {
+                    match options.method {
+                        FillMethod::Linear => fill_linear(c3d, column, before, after),
+                        FillMethod::Spline => fill_spline(c3d, column, before, after),
+                        FillMethod::Pattern => {
+                            fill_pattern(c3d, column, donor.unwrap(), before, after)
+                        }
+                    }
+                }
+                _ => false,
+            };
+            if filled {
+                summary.filled_gaps += 1;
+                summary.filled_frames += gap.length;
+            } else {
+                summary.skipped_gaps += 1;
+            }
+        }
+        if summary.filled_gaps + summary.skipped_gaps > 0 {
+            summaries.push(summary);
+        }
+    }
+    Ok(summaries)
+}
+
+fn marker_column(c3d: &C3d, label: &str) -> Result<usize, String> {
+    c3d.points
+        .labels
+        .iter()
+        .position(|x| x.trim() == label.trim())
+        .filter(|column| *column < c3d.points.cols())
+        .ok_or(format!("{} is not a marker in the file", label))
+}
+
+fn interpolated_point(point: [f32; 3]) -> MarkerPoint {
+    // a zero residual marks the point as interpolated
+    MarkerPoint::new(point[0], point[1], point[2])
+}
+
+/// Fills the frames between `before` and `after` on a straight line.
+fn fill_linear(c3d: &mut C3d, column: usize, before: usize, after: usize) -> bool {
+    let start = c3d.points[(before, column)].point;
+    let end = c3d.points[(after, column)].point;
+    let span = (after - before) as f32;
+    for row in before + 1..after {
+        let t = (row - before) as f32 / span;
+        let mut point = [0f32; 3];
+        for axis in 0..3 {
+            point[axis] = start[axis] + t * (end[axis] - start[axis]);
+        }
+        c3d.points[(row, column)] = interpolated_point(point);
+    }
+    true
+}
+
+/// Fills the frames between `before` and `after` with a natural cubic spline
+/// fitted to the valid frames on either side of the gap.
+fn fill_spline(c3d: &mut C3d, column: usize, before: usize, after: usize) -> bool {
+    let is_valid = |row: usize| !crate::gaps::is_gap(&c3d.points[(row, column)]);
+    let mut rows: Vec<usize> = (0..=before)
+        .rev()
+        .take(SPLINE_CONTEXT)
+        .take_while(|row| is_valid(*row))
+        .collect();
+    rows.reverse();
+    rows.extend(
+        (after..c3d.points.rows())
+            .take(SPLINE_CONTEXT)
+            .take_while(|row| is_valid(*row)),
+    );
+    let xs: Vec<f64> = rows.iter().map(|row| *row as f64).collect();
+    let mut filled = vec![[0f32; 3]; after - before - 1];
+    for axis in 0..3 {
+        let ys: Vec<f64> = rows
+            .iter()
+            .map(|row| c3d.points[(*row, column)][axis] as f64)
+            .collect();
+        let second_derivatives = natural_spline(&xs, &ys);
+        for (i, row) in (before + 1..after).enumerate() {
+            filled[i][axis] = evaluate_spline(&xs, &ys, &second_derivatives, row as f64) as f32;
+        }
+    }
+    for (i, row) in (before + 1..after).enumerate() {
+        c3d.points[(row, column)] = interpolated_point(filled[i]);
+    }
+    true
+}
+
+/// Fills the frames between `before` and `after` by following the donor marker,
+/// blending the offset to the donor linearly across the gap.
+/// Returns false if the donor is missing anywhere in the gap.
+fn fill_pattern(c3d: &mut C3d, column: usize, donor: usize, before: usize, after: usize) -> bool {
+    if (before..=after).any(|row| crate::gaps::is_gap(&c3d.points[(row, donor)])) {
+        return false;
+    }
+    let start = c3d.points[(before, column)].point;
+    let end = c3d.points[(after, column)].point;
+    let donor_start = c3d.points[(before, donor)].point;
+    let donor_end = c3d.points[(after, donor)].point;
+    let span = (after - before) as f32;
+    for row in before + 1..after {
+        let t = (row - before) as f32 / span;
+        let donor_point = c3d.points[(row, donor)].point;
+        let mut point = [0f32; 3];
+        for axis in 0..3 {
+            let offset_start = start[axis] - donor_start[axis];
+            let offset_end = end[axis] - donor_end[axis];
+            point[axis] = donor_point[axis] + offset_start + t * (offset_end - offset_start);
+        }
+        c3d.points[(row, column)] = interpolated_point(point);
+    }
+    true
+}
+
+/// Computes the second derivatives of a natural cubic spline through the points.
+fn natural_spline(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    let n = xs.len();
+    let mut second = vec![0.; n];
+    if n < 3 {
+        return second;
+    }
+    let mut u = vec![0.; n];
+    for i in 1..n - 1 {
+        let sig = (xs[i] - xs[i - 1]) / (xs[i + 1] - xs[i - 1]);
+        let p = sig * second[i - 1] + 2.;
+        second[i] = (sig - 1.) / p;
+        let slope =
+            (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i]) - (ys[i] - ys[i - 1]) / (xs[i] - xs[i - 1]);
+        u[i] = (6. * slope / (xs[i + 1] - xs[i - 1]) - sig * u[i - 1]) / p;
+    }
+    for i in (0..n - 1).rev() {
+        second[i] = second[i] * second[i + 1] + u[i];
+    }
+    second
+}
+
+fn evaluate_spline(xs: &[f64], ys: &[f64], second: &[f64], x: f64) -> f64 {
+    let hi = xs
+        .iter()
+        .position(|value| *value > x)
+        .unwrap_or(xs.len() - 1)
+        .max(1);
+    let lo = hi - 1;
+    let h = xs[hi] - xs[lo];
+    let a = (xs[hi] - x) / h;
+    let b = (x - xs[lo]) / h;
+    a * ys[lo]
+        + b * ys[hi]
+        + ((a * a * a - a) * second[lo] + (b * b * b - b) * second[hi]) * h * h / 6.
+}
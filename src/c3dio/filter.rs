@@ -0,0 +1,323 @@
+use c3dio::prelude::*;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::analog::select_channels;
+use crate::args::{file_arg, format_arg, jobs_arg, output_arg};
+use crate::batch::{check_output, find_files, finish_batch, job_count, run_batch, suffixed_name};
+use crate::error::CliError;
+use crate::forces::build_sto;
+use crate::gaps::is_gap;
+
+pub(super) fn filter_command() -> Command {
+    Command::new("filter")
+        .about("Applies a zero-lag low-pass Butterworth filter to marker and analog data")
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            format_arg()
+                .default_value("c3d")
+                .help("The format to write: c3d, trc (markers) or sto (ground reactions)"),
+        )
+        .arg(
+            Arg::new("CUTOFF")
+                .short('c')
+                .long("cutoff")
+                .default_value("6")
+                .value_parser(clap::value_parser!(f64))
+                .help("The cutoff frequency in Hz"),
+        )
+        .arg(
+            Arg::new("ORDER")
+                .long("order")
+                .default_value("2")
+                .value_parser(clap::value_parser!(usize))
+                .help("The filter order of each pass, the forward-backward pass doubles it"),
+        )
+        .arg(
+            Arg::new("ANALOG")
+                .short('a')
+                .long("analog")
+                .help("A comma separated list of analog channels or glob patterns to filter, or \"all\""),
+        )
+        .arg(
+            Arg::new("ANALOG_CUTOFF")
+                .long("analog-cutoff")
+                .value_parser(clap::value_parser!(f64))
+                .help(
+                    "The cutoff frequency in Hz for analog channels, the marker cutoff by default",
+                ),
+        )
+        .arg(
+            Arg::new("NO_POINTS")
+                .long("no-points")
+                .action(ArgAction::SetTrue)
+                .help("Leave the marker data unfiltered"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_filter_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let format = sub_matches.get_one::<String>("FORMAT").unwrap();
+    let format = FilterOutputFileTypes::from_str(format).map_err(CliError::Arguments)?;
+    let cutoff = *sub_matches.get_one::<f64>("CUTOFF").unwrap();
+    let order = *sub_matches.get_one::<usize>("ORDER").unwrap();
+    if order == 0 {
+        return Err(CliError::Arguments("The filter order must be at least 1".to_string()));
+    }
+    let analog_cutoff = sub_matches
+        .get_one::<f64>("ANALOG_CUTOFF")
+        .copied()
+        .unwrap_or(cutoff);
+    let analog_channels: Option<Vec<String>> = sub_matches
+        .get_one::<String>("ANALOG")
+        .map(|channels| channels.split(',').map(|c| c.trim().to_string()).collect());
+    let filter_points = !sub_matches.get_flag("NO_POINTS");
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let (output, suffix): (PathBuf, &str) = match output {
+        Some(output) => (output.into(), ""),
+        None => {
+            // set output to current directory, named so the inputs are not replaced
+            println!(
+                "{}",
+                "No output file was provided, writing _filtered files to current directory".yellow()
+            );
+            (std::env::current_dir().map_err(CliError::write)?, "_filtered")
+        }
+    };
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        if filter_points {
+            let filter = Butterworth::low_pass(order, cutoff, c3d.points.frame_rate as f64)?;
+            filter_points_data(&mut c3d, &filter);
+        }
+        if let Some(channels) = &analog_channels {
+            let filter = Butterworth::low_pass(order, analog_cutoff, c3d.analog.rate as f64)?;
+            let columns = select_channels(&c3d, channels).map_err(CliError::Arguments)?;
+            filter_analog_data(&mut c3d, &columns, &filter);
+        }
+        let output = match output.is_dir() {
+            true => {
+                let mut output = output.join(suffixed_name(file, suffix));
+                output.set_extension(format.to_string());
+                output
+            }
+            false => output.clone(),
+        };
+        check_output(file, &output)?;
+        let write_attempt = match format {
+            FilterOutputFileTypes::C3d => c3d.write_path(output.clone()).map(|_| ()),
+            FilterOutputFileTypes::Trc => Trc::from_c3d(&c3d).write(output.clone()),
+            FilterOutputFileTypes::Sto => match build_sto(&c3d) {
+                Some(sto) => sto.write(output.clone()),
+                None => Err(C3dWriteError::InvalidForcePlatformInfo(
+                    "Could not build sto file from c3d file".to_string(),
+                )),
+            },
+        };
+        write_attempt.map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FilterOutputFileTypes {
+    C3d,
+    Trc,
+    Sto,
+}
+
+impl FromStr for FilterOutputFileTypes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "c3d" => Ok(FilterOutputFileTypes::C3d),
+            "trc" => Ok(FilterOutputFileTypes::Trc),
+            "sto" => Ok(FilterOutputFileTypes::Sto),
+            _ => Err(format!(
+                "{} is not a valid output file type, types allowed: .c3d, .trc, .sto",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for FilterOutputFileTypes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterOutputFileTypes::C3d => write!(f, "c3d"),
+            FilterOutputFileTypes::Trc => write!(f, "trc"),
+            FilterOutputFileTypes::Sto => write!(f, "sto"),
+        }
+    }
+}
+
+/// A second order section in transposed direct form II.
+/// First order sections leave `b2` and `a2` at zero.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Section {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Section {
+    /// Filters `data` in place, starting from the steady state of its first value.
+    fn apply(&self, data: &mut [f64]) {
+        let first = match data.first() {
+            Some(first) => *first,
+            None => return,
+        };
+        // the output a constant input settles to, the first value for low-pass and zero for high-pass
+        let settled = first * (self.b0 + self.b1 + self.b2) / (1. + self.a1 + self.a2);
+        let mut s2 = self.b2 * first - self.a2 * settled;
+        let mut s1 = self.b1 * first - self.a1 * settled + s2;
+        for x in data.iter_mut() {
+            let y = self.b0 * *x + s1;
+            s1 = self.b1 * *x - self.a1 * y + s2;
+            s2 = self.b2 * *x - self.a2 * y;
+            *x = y;
+        }
+    }
+}
+
+/// A digital Butterworth filter built from cascaded sections with the bilinear transform.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Butterworth {
+    order: usize,
+    sections: Vec<Section>,
+}
+
+impl Butterworth {
+    /// Designs a low-pass filter of the given order.
+    /// Fails if the cutoff is not between zero and the Nyquist frequency.
+    pub fn low_pass(order: usize, cutoff: f64, rate: f64) -> Result<Self, String> {
+        Butterworth::design(order, cutoff, rate, false)
+    }
+
+    /// Designs a high-pass filter of the given order.
+    /// Fails if the cutoff is not between zero and the Nyquist frequency.
+    pub fn high_pass(order: usize, cutoff: f64, rate: f64) -> Result<Self, String> {
+        Butterworth::design(order, cutoff, rate, true)
+    }
+
+    fn design(order: usize, cutoff: f64, rate: f64, high_pass: bool) -> Result<Self, String> {
+        if !(cutoff > 0. && cutoff < rate / 2.) {
+            return Err(format!(
+                "The cutoff of {} Hz must be above 0 and below half the sample rate of {} Hz",
+                cutoff, rate
+            ));
+        }
+        // prewarp the cutoff so the digital filter matches it exactly
+        let k = (std::f64::consts::PI * cutoff / rate).tan();
+        let mut sections = Vec::new();
+        for i in 0..order / 2 {
+            let angle = std::f64::consts::PI * (2 * i + 1) as f64 / (2 * order) as f64;
+            let q = 1. / (2. * angle.sin());
+            let norm = 1. / (1. + k / q + k * k);
+            let (b0, b1) = match high_pass {
+                true => (norm, -2. * norm),
+                false => (k * k * norm, 2. * k * k * norm),
+            };
+            sections.push(Section {
+                b0,
+                b1,
+                b2: b0,
+                a1: 2. * (k * k - 1.) * norm,
+                a2: (1. - k / q + k * k) * norm,
+            });
+        }
+        if order % 2 == 1 {
+            let norm = 1. / (1. + k);
+            let (b0, b1) = match high_pass {
+                true => (norm, -norm),
+                false => (k * norm, k * norm),
+            };
+            sections.push(Section {
+                b0,
+                b1,
+                b2: 0.,
+                a1: (k - 1.) * norm,
+                a2: 0.,
+            });
+        }
+        Ok(Butterworth { order, sections })
+    }
+
+    fn apply(&self, data: &mut [f64]) {
+        for section in &self.sections {
+            section.apply(data);
+        }
+    }
+
+    /// Filters `data` forwards and backwards so the result has no phase lag.
+    /// The ends are padded with an odd reflection of the data to reduce transients.
+    pub fn filtfilt(&self, data: &mut [f64]) {
+        if data.len() < 2 {
+            return;
+        }
+        let pad = (3 * (self.order + 1)).min(data.len() - 1);
+        let first = data[0];
+        let last = data[data.len() - 1];
+        let mut padded = Vec::with_capacity(data.len() + 2 * pad);
+        padded.extend((1..=pad).rev().map(|i| 2. * first - data[i]));
+        padded.extend_from_slice(data);
+        padded.extend((1..=pad).map(|i| 2. * last - data[data.len() - 1 - i]));
+        self.apply(&mut padded);
+        padded.reverse();
+        self.apply(&mut padded);
+        padded.reverse();
+        data.copy_from_slice(&padded[pad..pad + data.len()]);
+    }
+}
+
+/// Filters every marker trajectory, treating each run of valid frames separately
+/// so that gaps are left as they are and never smeared into the neighbouring data.
+pub(crate) fn filter_points_data(c3d: &mut C3d, filter: &Butterworth) {
+    for column in 0..c3d.points.cols() {
+        let mut row = 0;
+        while row < c3d.points.rows() {
+            if is_gap(&c3d.points[(row, column)]) {
+                row += 1;
+                continue;
+            }
+            let start = row;
+            while row < c3d.points.rows() && !is_gap(&c3d.points[(row, column)]) {
+                row += 1;
+            }
+            for axis in 0..3 {
+                let mut values: Vec<f64> = (start..row)
+                    .map(|r| c3d.points[(r, column)][axis] as f64)
+                    .collect();
+                filter.filtfilt(&mut values);
+                for (r, value) in (start..row).zip(values) {
+                    c3d.points[(r, column)].point[axis] = value as f32;
+                }
+            }
+        }
+    }
+}
+
+/// Filters the analog channels at the given columns.
+pub(crate) fn filter_analog_data(c3d: &mut C3d, columns: &[usize], filter: &Butterworth) {
+    for column in columns {
+        let mut values: Vec<f64> = c3d.analog.iter_col(*column).copied().collect();
+        filter.filtfilt(&mut values);
+        for (row, value) in values.into_iter().enumerate() {
+            c3d.analog[(row, *column)] = value;
+        }
+    }
+}
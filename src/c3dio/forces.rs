@@ -3,8 +3,15 @@ use std::fmt::Display;
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use crate::args::{file_arg, output_arg};
-use clap::{ArgMatches, Command};
+use crate::analog::export_start_time;
+use crate::args::{assign_arg, file_arg, left_markers_arg, output_arg, right_markers_arg};
+use crate::error::CliError;
+use crate::events::foot_assignment;
+use crate::file_formats::table::{Table, TableFormat};
+use crate::gait::{contact_foot, plate_normal, FootAssignment};
+use crate::math::{add, cross, dot, from_columns, mat_vec, normalize, scale, sub, Vector3};
+use crate::transform::{apply_transform, point_meters_per_unit, Transform};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use colored::Colorize;
 
 use c3dio::file_formats::sto;
@@ -12,55 +19,84 @@ use c3dio::prelude::*;
 
 pub(super) fn force_command() -> Command {
     Command::new("forces")
-        .about("Prints the force data from a C3D file")
+        .about("Writes the ground reaction forces, moments and centers of pressure from a C3D file")
         .arg(file_arg().required(true))
         .arg(output_arg().required(true))
+        .arg(
+            Arg::new("Y_UP")
+                .long("y-up")
+                .action(ArgAction::SetTrue)
+                .help("Rotate a Z-up lab frame to Y-up before writing"),
+        )
+        .arg(
+            // ground reactions are always written in meters, the flag is kept for older scripts
+            Arg::new("METERS")
+                .long("meters")
+                .action(ArgAction::SetTrue)
+                .hide(true),
+        )
+        .arg(
+            Arg::new("FEET")
+                .long("feet")
+                .action(ArgAction::SetTrue)
+                .help("Write r_ground_ and l_ground_ columns for each foot instead of columns for each plate"),
+        )
+        .arg(assign_arg().requires("FEET"))
+        .arg(left_markers_arg().requires("FEET"))
+        .arg(right_markers_arg().requires("FEET"))
 }
 
-pub(super) fn process_forces_command(sub_matches: ArgMatches) {
+pub(super) fn process_forces_command(sub_matches: ArgMatches) -> Result<(), CliError> {
     let file = sub_matches.get_one::<String>("FILE").unwrap();
     let output = sub_matches.get_one::<String>("OUTPUT").unwrap();
     let format = match output.split('.').last() {
-        Some(format) => {
-            let format = ForceOutputFileTypes::from_str(format.trim().to_lowercase().as_str());
-            match format {
-                Ok(format) => format,
-                Err(e) => {
-                    println!("{}", e.red());
-                    return;
-                }
-            }
-        }
+        Some(format) => ForceOutputFileTypes::from_str(format.trim().to_lowercase().as_str())
+            .map_err(CliError::Arguments)?,
         None => {
-            println!(
-                "{}",
-                "No output format was provided, please provide a file with an extension".red()
-            );
-            return;
+            return Err(CliError::Arguments(
+                "No output format was provided, please provide a file with an extension"
+                    .to_string(),
+            ))
         }
     };
+    let transform = Transform::export(
+        sub_matches.get_flag("Y_UP"),
+        sub_matches.get_flag("METERS"),
+    );
     println!("Opening {}", file.green());
-    match C3d::load(file) {
-        Ok(c3d) => {
-            println!("Converting to {}", format.to_string().bright_yellow());
-            let write_attempt = match format {
-                ForceOutputFileTypes::Sto => {
-                    let sto = build_sto(&c3d);
-                    match sto {
-                        Some(sto) => sto.write(PathBuf::from(output)),
-                        None => Err(C3dWriteError::InvalidForcePlatformInfo(
-                            "Could not build sto file from c3d file".to_string(),
-                        )),
-                    }
-                }
-            };
-            match write_attempt {
-                Ok(_) => println!("Wrote {}", output.green()),
-                Err(e) => println!("{}", e.to_string().red()),
+    let mut c3d = C3d::load(file).map_err(CliError::load)?;
+    if !transform.is_identity() {
+        apply_transform(&mut c3d, &transform)?;
+    }
+    println!("Converting to {}", format.to_string().bright_yellow());
+    match format {
+        ForceOutputFileTypes::Sto if sub_matches.get_flag("FEET") => {
+            let reactions = foot_reactions(&c3d, &foot_assignment(&sub_matches)).ok_or_else(|| {
+                CliError::Processing("The C3D file has no force plates with valid corners".to_string())
+            })?;
+            if reactions.unassigned > 0 {
+                println!(
+                    "{}",
+                    format!(
+                        "{} plate contacts could not be assigned to a foot and were left out",
+                        reactions.unassigned
+                    )
+                    .yellow()
+                );
             }
+            foot_table(&c3d, &reactions)
+                .write(PathBuf::from(output), TableFormat::Sto)
+                .map_err(CliError::write)?;
+        }
+        ForceOutputFileTypes::Sto => {
+            let sto = build_sto(&c3d).ok_or_else(|| {
+                CliError::Processing("Could not build sto file from c3d file".to_string())
+            })?;
+            sto.write(PathBuf::from(output)).map_err(CliError::write)?;
         }
-        Err(e) => println!("{}", e.to_string().red()),
     }
+    println!("Wrote {}", output.green());
+    Ok(())
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -87,35 +123,195 @@ impl Display for ForceOutputFileTypes {
     }
 }
 
-fn build_sto(c3d: &C3d) -> Option<Sto> {
+/// Below this vertical force (in newtons) the center of pressure is undefined,
+/// so the force, moment and center of pressure are reset to the plate center.
+pub(crate) const MIN_VERTICAL_FORCE: f64 = 10.0;
+
+/// The ground reaction of a single force plate for one analog sample, the load the
+/// plate applies to the foot, so the force points out of the plate surface.
+/// All values are expressed in the lab frame, positions in the point units of the file.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub(crate) struct GroundReaction {
+    pub force: Vector3,
+    pub center_of_pressure: Vector3,
+    pub torque: Vector3,
+}
+
+/// Computes the ground reaction force, center of pressure and free moment of a plate
+/// for every analog sample, using the plate type to interpret its channels.
+/// Returns `None` if the plate does not exist or its corners do not define a plane.
+pub(crate) fn ground_reactions(c3d: &C3d, plate_index: usize) -> Option<Vec<GroundReaction>> {
+    let plate = c3d.forces.get(plate_index)?;
+    let corners: Vec<Vector3> = plate
+        .corners
+        .iter()
+        .map(|corner| [corner[0] as f64, corner[1] as f64, corner[2] as f64])
+        .collect();
+    let center = scale(
+        add(add(corners[0], corners[1]), add(corners[2], corners[3])),
+        0.25,
+    );
+    // the plate x axis points from corner 2 to corner 1 and the y axis from corner 4 to corner 1
+    let x_axis = normalize(sub(corners[0], corners[1]))?;
+    let y_axis = sub(corners[0], corners[3]);
+    let z_axis = normalize(cross(x_axis, y_axis))?;
+    let y_axis = cross(z_axis, x_axis);
+    let rotation = from_columns(x_axis, y_axis, z_axis);
+
+    let origin = [
+        plate.origin[0] as f64,
+        plate.origin[1] as f64,
+        plate.origin[2] as f64,
+    ];
+    // ORIGIN is the position of the working surface center relative to the transducer.
+    // The surface is above the transducer, which is negative z in the plate frame,
+    // but many files store the vector with the opposite sign.
+    let surface = match plate.plate_type {
+        ForcePlatformType::Type3 => [0., 0., origin[2]],
+        _ => origin,
+    };
+    let surface = match surface[2] > 0. {
+        true => scale(surface, -1.),
+        false => surface,
+    };
+
+    let mut reactions = Vec::with_capacity(c3d.analog.rows());
+    for row in 0..c3d.analog.rows() {
+        let mut analog = [0f64; 8];
+        for (i, channel) in plate.channels.iter().enumerate() {
+            let channel = *channel as usize;
+            if channel > 0 && channel <= c3d.analog.cols() {
+                analog[i] = c3d.analog[row][channel - 1];
+            }
+        }
+        let (force, moment, local_cop, free_moment) = match plate.plate_type {
+            ForcePlatformType::Type1 => {
+                let force = [analog[0], analog[1], analog[2]];
+                let local_cop = [analog[3], analog[4], 0.];
+                (force, [0.; 3], Some(local_cop), Some(analog[5]))
+            }
+            ForcePlatformType::Type2 => (
+                [analog[0], analog[1], analog[2]],
+                [analog[3], analog[4], analog[5]],
+                None,
+                None,
+            ),
+            ForcePlatformType::Type3 => {
+                let (a, b) = (origin[0].abs(), origin[1].abs());
+                let force = [
+                    analog[0] + analog[1],
+                    analog[2] + analog[3],
+                    analog[4] + analog[5] + analog[6] + analog[7],
+                ];
+                let moment = [
+                    b * (analog[4] + analog[5] - analog[6] - analog[7]),
+                    a * (-analog[4] + analog[5] + analog[6] - analog[7]),
+                    b * (-analog[0] + analog[1]) + a * (analog[2] - analog[3]),
+                ];
+                (force, moment, None, None)
+            }
+            ForcePlatformType::Type4 => {
+                // CAL_MATRIX is stored column first, so each inner array is a column
+                let calibrated = match plate.cal_matrix {
+                    Some(cal_matrix) => {
+                        let mut calibrated = [0f64; 6];
+                        for (column, values) in cal_matrix.iter().enumerate() {
+                            for (row, value) in values.iter().enumerate() {
+                                calibrated[row] += *value as f64 * analog[column];
+                            }
+                        }
+                        calibrated
+                    }
+                    None => [
+                        analog[0], analog[1], analog[2], analog[3], analog[4], analog[5],
+                    ],
+                };
+                (
+                    [calibrated[0], calibrated[1], calibrated[2]],
+                    [calibrated[3], calibrated[4], calibrated[5]],
+                    None,
+                    None,
+                )
+            }
+        };
+        if force[2].abs() < MIN_VERTICAL_FORCE {
+            reactions.push(GroundReaction {
+                force: [0.; 3],
+                center_of_pressure: center,
+                torque: [0.; 3],
+            });
+            continue;
+        }
+        let (local_cop, free_moment) = match (local_cop, free_moment) {
+            (Some(local_cop), Some(free_moment)) => (local_cop, free_moment),
+            _ => {
+                // moments are about the transducer, the force acts on the working surface
+                let x = (surface[2] * force[0] - moment[1]) / force[2];
+                let y = (moment[0] + surface[2] * force[1]) / force[2];
+                let free_moment = moment[2] - (x * force[1] - y * force[0]);
+                ([x - surface[0], y - surface[1], 0.], free_moment)
+            }
+        };
+        // plates measure the load on them and their z axis points into the floor,
+        // so the reaction on the foot is the load with its z component made negative
+        let (force, free_moment) = match force[2] > 0. {
+            true => (scale(force, -1.), -free_moment),
+            false => (force, free_moment),
+        };
+        reactions.push(GroundReaction {
+            force: mat_vec(&rotation, force),
+            center_of_pressure: add(center, mat_vec(&rotation, local_cop)),
+            torque: mat_vec(&rotation, [0., 0., free_moment]),
+        });
+    }
+    Some(reactions)
+}
+
+/// Builds an STO file with the ground reactions of every plate. OpenSim reads
+/// positions in meters, so centers of pressure and torques are written in meters
+/// whatever the units of the file.
+pub(crate) fn build_sto(c3d: &C3d) -> Option<Sto> {
+    let meters = point_meters_per_unit(c3d);
     let mut column_names = Vec::new();
     let mut data = Grid::new(c3d.analog.analog.size().0, 0);
     if c3d.forces.len() > 0 {
-        for (i, plate) in c3d.forces.iter().enumerate() {
-            for channel in plate.channels.into_iter() {
-                let channel = channel as usize;
-                if c3d.analog.labels.len() >= channel {
-                    column_names.push(c3d.analog.labels[channel - 1].clone());
-                } else {
-                    column_names.push(format!("column_{}", channel));
+        for i in 0..c3d.forces.len() {
+            let reactions = match ground_reactions(c3d, i) {
+                Some(reactions) => reactions,
+                None => {
+                    println!(
+                        "{}",
+                        format!("Force plate {} has invalid corners, skipping", i + 1).red()
+                    );
+                    continue;
                 }
-                data.push_col(c3d.analog.iter_col(channel - 1).cloned().collect());
+            };
+            let prefix = format!("{}_ground_", i + 1);
+            for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+                column_names.push(format!("{}force_v{}", prefix, name));
+                data.push_col(reactions.iter().map(|r| r.force[axis]).collect());
+            }
+            for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+                column_names.push(format!("{}force_p{}", prefix, name));
+                data.push_col(
+                    reactions
+                        .iter()
+                        .map(|r| r.center_of_pressure[axis] * meters)
+                        .collect(),
+                );
             }
-            let origin = plate.origin.as_ref();
-            if origin.len() >= i + 1 {
-                column_names.push(format!("EC{}X", i + 1));
-                column_names.push(format!("EC{}Y", i + 1));
-                column_names.push(format!("EC{}Z", i + 1));
-                data.push_col(vec![origin[0] as f64; data.size().0]);
-                data.push_col(vec![origin[1] as f64; data.size().0]);
-                data.push_col(vec![origin[2] as f64; data.size().0]);
+            for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+                column_names.push(format!("{}torque_{}", prefix, name));
+                data.push_col(reactions.iter().map(|r| r.torque[axis] * meters).collect());
             }
         }
         Some(Sto {
             file_description: None,
             version: 1,
             in_degrees: false,
-            first_frame: c3d.points.first_frame as usize,
+            // analog rows start at the same time as the first point frame
+            first_frame: c3d.points.first_frame as usize
+                * c3d.analog.samples_per_channel_per_frame.max(1) as usize,
             column_names,
             data_rate: c3d.analog.rate,
             data,
@@ -125,3 +321,131 @@ fn build_sto(c3d: &C3d) -> Option<Sto> {
         None
     }
 }
+
+/// The ground reactions of each foot, combined over the plates it stood on.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FootReactions {
+    pub left: Vec<GroundReaction>,
+    pub right: Vec<GroundReaction>,
+    /// Plate contacts left out because the markers or events did not name a foot.
+    pub unassigned: usize,
+}
+
+/// Assigns every loaded period of every plate to a foot, from the markers nearest to
+/// its mean center of pressure or from the events, and combines the plates under each foot.
+/// Where a foot loads two plates at once the center of pressure is weighted by the
+/// vertical forces and the torque is taken about it. Unloaded feet have zero reactions.
+/// Returns `None` if no plate has valid corners.
+pub(crate) fn foot_reactions(c3d: &C3d, assignment: &FootAssignment) -> Option<FootReactions> {
+    let rows = c3d.analog.rows();
+    // force, vertical force, vertical force times center of pressure, torque and
+    // center of pressure cross force, summed for each foot and row
+    let mut sums = [vec![[[0.; 3]; 4]; rows], vec![[[0.; 3]; 4]; rows]];
+    let mut weights = [vec![0.; rows], vec![0.; rows]];
+    let mut unassigned = 0;
+    let mut valid = false;
+    for (index, plate) in c3d.forces.iter().enumerate() {
+        let (reactions, normal) = match (ground_reactions(c3d, index), plate_normal(plate)) {
+            (Some(reactions), Some(normal)) => (reactions, normal),
+            _ => continue,
+        };
+        valid = true;
+        let mut row = 0;
+        while row < reactions.len() {
+            if reactions[row].force == [0.; 3] {
+                row += 1;
+                continue;
+            }
+            let start = row;
+            while row < reactions.len() && reactions[row].force != [0.; 3] {
+                row += 1;
+            }
+            let contact = &reactions[start..row];
+            let center_of_pressure = contact
+                .iter()
+                .fold([0.; 3], |sum, r| add(sum, r.center_of_pressure));
+            let center_of_pressure = scale(center_of_pressure, 1. / contact.len() as f64);
+            let foot = match contact_foot(c3d, assignment, (start, row), center_of_pressure, normal) {
+                Some("Left") => 0,
+                Some(_) => 1,
+                None => {
+                    unassigned += 1;
+                    continue;
+                }
+            };
+            for (offset, reaction) in contact.iter().enumerate() {
+                // the normal points into the floor, against the reaction
+                let weight = -dot(reaction.force, normal);
+                let sum = &mut sums[foot][start + offset];
+                sum[0] = add(sum[0], reaction.force);
+                sum[1] = add(sum[1], scale(reaction.center_of_pressure, weight));
+                sum[2] = add(sum[2], reaction.torque);
+                sum[3] = add(sum[3], cross(reaction.center_of_pressure, reaction.force));
+                weights[foot][start + offset] += weight;
+            }
+        }
+    }
+    if !valid {
+        return None;
+    }
+    let combine = |foot: usize| {
+        sums[foot]
+            .iter()
+            .zip(weights[foot].iter())
+            .map(|(sum, weight)| match *weight > 0. {
+                true => {
+                    let center_of_pressure = scale(sum[1], 1. / weight);
+                    GroundReaction {
+                        force: sum[0],
+                        center_of_pressure,
+                        // moments of every plate moved to the combined center of pressure
+                        torque: sub(add(sum[2], sum[3]), cross(center_of_pressure, sum[0])),
+                    }
+                }
+                false => GroundReaction::default(),
+            })
+            .collect::<Vec<_>>()
+    };
+    Some(FootReactions {
+        left: combine(0),
+        right: combine(1),
+        unassigned,
+    })
+}
+
+/// Builds a table of the right and left foot reactions with the `r_ground_` and
+/// `l_ground_` column names OpenSim external loads refer to, in meters like `build_sto`.
+pub(crate) fn foot_table(c3d: &C3d, reactions: &FootReactions) -> Table {
+    let meters = point_meters_per_unit(c3d);
+    let mut column_names = Vec::new();
+    let mut data = Grid::new(c3d.analog.rows(), 0);
+    for (prefix, reactions) in [("r_ground_", &reactions.right), ("l_ground_", &reactions.left)] {
+        // grid cannot take empty columns
+        if reactions.is_empty() {
+            continue;
+        }
+        for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+            column_names.push(format!("{}force_v{}", prefix, name));
+            data.push_col(reactions.iter().map(|r| r.force[axis]).collect());
+        }
+        for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+            column_names.push(format!("{}force_p{}", prefix, name));
+            data.push_col(
+                reactions
+                    .iter()
+                    .map(|r| r.center_of_pressure[axis] * meters)
+                    .collect(),
+            );
+        }
+        for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+            column_names.push(format!("{}torque_{}", prefix, name));
+            data.push_col(reactions.iter().map(|r| r.torque[axis] * meters).collect());
+        }
+    }
+    Table {
+        start_time: export_start_time(c3d),
+        data_rate: c3d.analog.rate as f64,
+        column_names,
+        data,
+    }
+}
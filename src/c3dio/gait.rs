@@ -0,0 +1,486 @@
+//! Gait event detection shared by the `events detect` command and the visualizer.
+use c3dio::prelude::*;
+use c3dio::{Event, ForcePlatform};
+
+use crate::events::{new_event, MAX_HEADER_EVENTS};
+use crate::forces::{ground_reactions, MIN_VERTICAL_FORCE};
+use crate::gaps::is_gap;
+use crate::math::{add, cross, dot, norm, normalize, scale, sub, Vector3};
+use crate::trim::frame_time;
+
+pub(crate) const FOOT_STRIKE: &str = "Foot Strike";
+pub(crate) const FOOT_OFF: &str = "Foot Off";
+
+/// The shortest time between two events of the same kind on the same foot.
+/// Strides are longer than this at any walking or running speed.
+const MIN_EVENT_INTERVAL: f32 = 0.3;
+
+/// A detected event, with its time in seconds on the C3D event time base.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GaitEvent {
+    pub context: String,
+    pub label: &'static str,
+    pub time: f32,
+}
+
+/// The markers used to tell which foot is on the ground.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FootMarkers {
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+}
+
+impl Default for FootMarkers {
+    fn default() -> Self {
+        FootMarkers {
+            left: vec!["LHEE".to_string(), "LTOE".to_string()],
+            right: vec!["RHEE".to_string(), "RTOE".to_string()],
+        }
+    }
+}
+
+/// Returns "Left" or "Right" for the foot whose markers are closest to `position`
+/// in the plane of the floor, or `None` if neither foot has a visible marker at `row`.
+pub(crate) fn nearest_foot(
+    c3d: &C3d,
+    row: usize,
+    position: Vector3,
+    normal: Vector3,
+    markers: &FootMarkers,
+) -> Option<&'static str> {
+    let distance = |labels: &[String]| {
+        labels
+            .iter()
+            .filter_map(|label| {
+                let column = c3d
+                    .points
+                    .labels
+                    .iter()
+                    .position(|l| l.trim() == label.trim())
+                    .filter(|column| *column < c3d.points.cols())?;
+                let point = c3d.points.get(row, column).filter(|p| !is_gap(p))?;
+                let offset = sub(
+                    [point[0] as f64, point[1] as f64, point[2] as f64],
+                    position,
+                );
+                // drop the height above the floor so a lifted foot is not penalised
+                Some(norm(sub(offset, scale(normal, dot(offset, normal)))))
+            })
+            .fold(None, |min: Option<f64>, d| {
+                Some(min.map_or(d, |m| m.min(d)))
+            })
+    };
+    match (distance(&markers.left), distance(&markers.right)) {
+        (Some(left), Some(right)) if left <= right => Some("Left"),
+        (Some(_), Some(_)) => Some("Right"),
+        (Some(_), None) => Some("Left"),
+        (None, Some(_)) => Some("Right"),
+        (None, None) => None,
+    }
+}
+
+/// How a force plate contact is assigned to the left or right foot.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum FootAssignment {
+    /// The foot whose markers are nearest to the center of pressure.
+    Markers(FootMarkers),
+    /// The foot of the foot strike at the start, or the foot off at the end, of the contact.
+    Events,
+}
+
+/// How far in seconds an event may be from the start or end of a contact to name its foot.
+const MAX_EVENT_OFFSET: f32 = 0.1;
+
+/// Returns "Left" or "Right" for a plate contact spanning the analog rows `start..end`,
+/// or `None` if the markers or events do not tell.
+pub(crate) fn contact_foot(
+    c3d: &C3d,
+    assignment: &FootAssignment,
+    (start, end): (usize, usize),
+    center_of_pressure: Vector3,
+    normal: Vector3,
+) -> Option<&'static str> {
+    match assignment {
+        FootAssignment::Markers(markers) => {
+            let samples = c3d.analog.samples_per_channel_per_frame.max(1) as usize;
+            let row = ((start + end) / 2 / samples).min(c3d.points.rows().saturating_sub(1));
+            nearest_foot(c3d, row, center_of_pressure, normal, markers)
+        }
+        FootAssignment::Events => {
+            let start_time = (c3d.points.first_frame as f32 - 1.) / c3d.points.frame_rate;
+            let sample_time = |row: usize| start_time + row as f32 / c3d.analog.rate;
+            // contacts already in progress at the start or end of the trial have only one event
+            let mut bounds = Vec::new();
+            if start > 0 {
+                bounds.push((FOOT_STRIKE, sample_time(start)));
+            }
+            if end < c3d.analog.rows() {
+                bounds.push((FOOT_OFF, sample_time(end)));
+            }
+            c3d.events
+                .iter()
+                .filter_map(|event| {
+                    let foot = match event.context.trim().to_lowercase().as_str() {
+                        "left" => "Left",
+                        "right" => "Right",
+                        _ => return None,
+                    };
+                    bounds
+                        .iter()
+                        .filter(|(label, _)| event.label.trim().eq_ignore_ascii_case(label))
+                        .map(|(_, time)| (event.time - time).abs())
+                        .filter(|offset| *offset <= MAX_EVENT_OFFSET)
+                        .reduce(f32::min)
+                        .map(|offset| (foot, offset))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(foot, _)| foot)
+        }
+    }
+}
+
+/// The unit normal of a force plate surface in the lab frame, along the plate z axis
+/// and so pointing into the floor.
+pub(crate) fn plate_normal(plate: &ForcePlatform) -> Option<Vector3> {
+    let corners: Vec<Vector3> = plate
+        .corners
+        .iter()
+        .map(|corner| [corner[0] as f64, corner[1] as f64, corner[2] as f64])
+        .collect();
+    let x_axis = sub(corners[0], corners[1]);
+    let y_axis = sub(corners[0], corners[3]);
+    normalize(cross(x_axis, y_axis))
+}
+
+/// Settings for force plate event detection.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ForceDetection {
+    /// The vertical force in newtons above which a foot is on the plate.
+    pub threshold: f64,
+    /// How far the force has to drop below the threshold before the foot is off the plate.
+    pub hysteresis: f64,
+    pub markers: FootMarkers,
+}
+
+impl ForceDetection {
+    /// Fails if the foot off level, the threshold minus the hysteresis, is below
+    /// `MIN_VERTICAL_FORCE`, where the plate already reports no load.
+    pub fn check(&self) -> Result<(), String> {
+        match self.threshold - self.hysteresis < MIN_VERTICAL_FORCE {
+            true => Err(format!(
+                "The threshold minus the hysteresis is {} N, it must be at least {} N since lower forces read as no load",
+                self.threshold - self.hysteresis,
+                MIN_VERTICAL_FORCE
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Default for ForceDetection {
+    fn default() -> Self {
+        ForceDetection {
+            threshold: 20.,
+            hysteresis: 5.,
+            markers: FootMarkers::default(),
+        }
+    }
+}
+
+/// Finds foot strikes and foot offs from the vertical force of every plate.
+/// A contact starts when the force rises above the threshold and ends when it falls
+/// below the threshold minus the hysteresis. Contacts already in progress at the start
+/// or end of the trial only produce the event that was observed.
+pub(crate) fn detect_force_events(c3d: &C3d, options: &ForceDetection) -> Vec<GaitEvent> {
+    let samples = c3d.analog.samples_per_channel_per_frame.max(1) as usize;
+    let start_time = (c3d.points.first_frame as f32 - 1.) / c3d.points.frame_rate;
+    let sample_time = |row: usize| start_time + row as f32 / c3d.analog.rate;
+    let off_threshold = (options.threshold - options.hysteresis).max(0.);
+    let mut events = Vec::new();
+    for (index, plate) in c3d.forces.iter().enumerate() {
+        let (reactions, normal) = match (ground_reactions(c3d, index), plate_normal(plate)) {
+            (Some(reactions), Some(normal)) => (reactions, normal),
+            _ => continue,
+        };
+        let vertical: Vec<f64> = reactions
+            .iter()
+            .map(|r| -dot(r.force, normal))
+            .collect();
+        let mut contacts: Vec<(Option<usize>, Option<usize>)> = Vec::new();
+        let mut in_contact = vertical.first().map_or(false, |f| *f > options.threshold);
+        if in_contact {
+            contacts.push((None, None));
+        }
+        for (row, force) in vertical.iter().enumerate().skip(1) {
+            if !in_contact && *force > options.threshold {
+                in_contact = true;
+                contacts.push((Some(row), None));
+            } else if in_contact && *force < off_threshold {
+                in_contact = false;
+                if let Some(contact) = contacts.last_mut() {
+                    contact.1 = Some(row);
+                }
+            }
+        }
+        for (strike, off) in contacts {
+            let first = strike.unwrap_or(0);
+            let last = off.unwrap_or(reactions.len()).max(first + 1);
+            let center_of_pressure = reactions[first..last]
+                .iter()
+                .fold([0.; 3], |sum, r| add(sum, r.center_of_pressure));
+            let center_of_pressure = scale(center_of_pressure, 1. / (last - first) as f64);
+            let row = ((first + last) / 2 / samples).min(c3d.points.rows().saturating_sub(1));
+            let context = nearest_foot(c3d, row, center_of_pressure, normal, &options.markers)
+                .unwrap_or("General");
+            if let Some(strike) = strike {
+                events.push(GaitEvent {
+                    context: context.to_string(),
+                    label: FOOT_STRIKE,
+                    time: sample_time(strike),
+                });
+            }
+            if let Some(off) = off {
+                events.push(GaitEvent {
+                    context: context.to_string(),
+                    label: FOOT_OFF,
+                    time: sample_time(off),
+                });
+            }
+        }
+    }
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    events
+}
+
+/// Writes detected events into the file, optionally removing the existing
+/// foot strike and foot off events first. Only the first events that fit in the
+/// header are kept, the number of later events left out is returned.
+pub(crate) fn write_gait_events(c3d: &mut C3d, events: &[GaitEvent], replace: bool) -> usize {
+    // the file is only changed once the complete list is known to fit
+    let mut written: Vec<Event> = c3d
+        .events
+        .iter()
+        .filter(|event| {
+            let label = event.label.trim();
+            !replace
+                || (!label.eq_ignore_ascii_case(FOOT_STRIKE)
+                    && !label.eq_ignore_ascii_case(FOOT_OFF))
+        })
+        .cloned()
+        .collect();
+    written.extend(
+        events
+            .iter()
+            .map(|event| new_event(c3d, &event.context, event.label, event.time)),
+    );
+    written.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let dropped = written.len().saturating_sub(MAX_HEADER_EVENTS);
+    written.truncate(MAX_HEADER_EVENTS);
+    *c3d.events = written;
+    c3d.events.supports_events_labels = true;
+    dropped
+}
+
+/// How kinematic detection finds the events, after Zeni et al. (2008).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum KinematicMethod {
+    /// Foot strikes at the most anterior heel position and foot offs at the most
+    /// posterior toe position relative to the sacrum.
+    Coordinate,
+    /// Foot strikes where the heel velocity relative to the sacrum turns from anterior to
+    /// posterior and foot offs where the toe velocity turns from posterior to anterior.
+    Velocity,
+}
+
+/// Settings for marker based event detection.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KinematicDetection {
+    pub method: KinematicMethod,
+    /// The heel and toe marker of each foot, in that order.
+    pub markers: FootMarkers,
+    /// The markers averaged into the sacrum position, for example SACR or LPSI and RPSI.
+    pub sacrum: Vec<String>,
+    /// The index of the vertical lab axis.
+    pub up: usize,
+}
+
+impl Default for KinematicDetection {
+    fn default() -> Self {
+        KinematicDetection {
+            method: KinematicMethod::Coordinate,
+            markers: FootMarkers::default(),
+            sacrum: vec!["SACR".to_string(), "LPSI".to_string(), "RPSI".to_string()],
+            up: 2,
+        }
+    }
+}
+
+/// Returns the trajectory of a marker, `None` in the frames where it is missing.
+fn trajectory(c3d: &C3d, label: &str) -> Result<Vec<Option<Vector3>>, String> {
+    let column = c3d
+        .points
+        .labels
+        .iter()
+        .position(|l| l.trim() == label.trim())
+        .filter(|column| *column < c3d.points.cols())
+        .ok_or(format!("{} is not a marker in the file", label))?;
+    Ok(c3d
+        .points
+        .iter_col(column)
+        .map(|p| match is_gap(p) {
+            true => None,
+            false => Some([p[0] as f64, p[1] as f64, p[2] as f64]),
+        })
+        .collect())
+}
+
+/// Finds foot strikes and foot offs from the heel, toe and sacrum markers, which also
+/// works for treadmill trials and strides that miss the force plates.
+/// The direction of progression is the mean heel to toe direction in the floor plane.
+pub(crate) fn detect_kinematic_events(
+    c3d: &C3d,
+    options: &KinematicDetection,
+) -> Result<Vec<GaitEvent>, String> {
+    let sacrum_markers = options
+        .sacrum
+        .iter()
+        .filter(|label| c3d.points.labels.iter().any(|l| l.trim() == label.trim()))
+        .map(|label| trajectory(c3d, label))
+        .collect::<Result<Vec<_>, _>>()?;
+    if sacrum_markers.is_empty() {
+        return Err(format!(
+            "None of the sacrum markers {} are in the file",
+            options.sacrum.join(", ")
+        ));
+    }
+    let sacrum: Vec<Option<Vector3>> = (0..c3d.points.rows())
+        .map(|row| {
+            let visible: Vec<Vector3> = sacrum_markers.iter().filter_map(|m| m[row]).collect();
+            match visible.len() {
+                0 => None,
+                n => Some(scale(
+                    visible.iter().fold([0.; 3], |s, p| add(s, *p)),
+                    1. / n as f64,
+                )),
+            }
+        })
+        .collect();
+    let mut up = [0.; 3];
+    up[options.up.min(2)] = 1.;
+
+    let mut feet = Vec::new();
+    for (side, labels) in [
+        ("Left", &options.markers.left),
+        ("Right", &options.markers.right),
+    ] {
+        match (labels.first(), labels.get(1)) {
+            (Some(heel), Some(toe)) => {
+                feet.push((side, trajectory(c3d, heel)?, trajectory(c3d, toe)?))
+            }
+            _ => return Err(format!("The {} foot needs a heel and a toe marker", side)),
+        }
+    }
+    let foot_direction = feet
+        .iter()
+        .flat_map(|(_, heel, toe)| heel.iter().zip(toe.iter()))
+        .filter_map(|(heel, toe)| Some(sub((*toe)?, (*heel)?)))
+        .fold([0.; 3], add);
+    let progression = normalize(sub(foot_direction, scale(up, dot(foot_direction, up))))
+        .ok_or("The direction of progression could not be found from the foot markers")?;
+
+    let rate = c3d.points.frame_rate;
+    let first_frame = c3d.points.first_frame as usize;
+    let window = ((MIN_EVENT_INTERVAL * rate / 2.).round() as usize).max(1);
+    let mut events = Vec::new();
+    for (side, heel, toe) in feet {
+        let relative = |marker: &Vec<Option<Vector3>>| -> Vec<Option<f64>> {
+            marker
+                .iter()
+                .zip(sacrum.iter())
+                .map(|(m, s)| Some(dot(sub((*m)?, (*s)?), progression)))
+                .collect()
+        };
+        let (strikes, offs) = match options.method {
+            KinematicMethod::Coordinate => (
+                extrema(&relative(&heel), window, true),
+                extrema(&relative(&toe), window, false),
+            ),
+            KinematicMethod::Velocity => (
+                zero_crossings(&velocity(&relative(&heel)), true),
+                zero_crossings(&velocity(&relative(&toe)), false),
+            ),
+        };
+        for (label, rows) in [(FOOT_STRIKE, strikes), (FOOT_OFF, offs)] {
+            let mut last: Option<f32> = None;
+            for row in rows {
+                let time = frame_time(c3d, first_frame) + row as f32 / rate;
+                if last.map_or(true, |last| time - last >= MIN_EVENT_INTERVAL) {
+                    events.push(GaitEvent {
+                        context: side.to_string(),
+                        label,
+                        time,
+                    });
+                    last = Some(time);
+                }
+            }
+        }
+    }
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(events)
+}
+
+/// Finds the rows that are the largest (or smallest) value within `window` rows on either
+/// side, skipping any window with missing values.
+fn extrema(values: &[Option<f64>], window: usize, maximum: bool) -> Vec<f32> {
+    let mut rows = Vec::new();
+    for row in window..values.len().saturating_sub(window) {
+        let neighbours: Option<Vec<f64>> = values[row - window..=row + window]
+            .iter()
+            .copied()
+            .collect();
+        let (neighbours, value) = match (neighbours, values[row]) {
+            (Some(neighbours), Some(value)) => (neighbours, value),
+            _ => continue,
+        };
+        let is_extremum = neighbours.iter().all(|v| match maximum {
+            true => *v <= value,
+            false => *v >= value,
+        });
+        // on a plateau only the first row counts
+        if is_extremum && neighbours[window - 1] != value {
+            rows.push(row as f32);
+        }
+    }
+    rows
+}
+
+/// The central difference of each row in units per frame.
+fn velocity(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|row| {
+            let before = values.get(row.checked_sub(1)?)?;
+            let after = values.get(row + 1)?;
+            Some(((*after)? - (*before)?) / 2.)
+        })
+        .collect()
+}
+
+/// Finds where the values change sign, from positive to negative when `falling`, and
+/// returns the row of the crossing interpolated between frames.
+fn zero_crossings(values: &[Option<f64>], falling: bool) -> Vec<f32> {
+    let mut rows = Vec::new();
+    for row in 1..values.len() {
+        let (before, after) = match (values[row - 1], values[row]) {
+            (Some(before), Some(after)) => (before, after),
+            _ => continue,
+        };
+        let crossed = match falling {
+            true => before > 0. && after <= 0.,
+            false => before < 0. && after >= 0.,
+        };
+        if crossed {
+            rows.push((row - 1) as f32 + (before / (before - after)) as f32);
+        }
+    }
+    rows
+}
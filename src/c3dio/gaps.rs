@@ -0,0 +1,298 @@
+use c3dio::prelude::*;
+use clap::{ArgMatches, Command};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::args::{file_arg, format_arg, jobs_arg, output_arg};
+use crate::batch::{find_files, job_count, run_batch, split_batch};
+use crate::error::CliError;
+
+pub(super) fn gaps_command() -> Command {
+    Command::new("gaps")
+        .about("Reports the frames where markers are missing or interpolated in C3D files")
+        .arg(file_arg().required(true))
+        .arg(format_arg().default_value("table"))
+        .arg(output_arg())
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_gaps_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let format = sub_matches.get_one::<String>("FORMAT").unwrap();
+    let format = GapReportFormat::from_str(format).map_err(CliError::Arguments)?;
+    let output = sub_matches.get_one::<String>("OUTPUT").map(PathBuf::from);
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        if output.is_some() || format == GapReportFormat::Table {
+            progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        }
+        let c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        Ok(GapReport::from_c3d(file.to_path_buf(), &c3d))
+    });
+    // the reports of the files that loaded are still written when others fail
+    let (reports, error) = split_batch(results);
+    let report = match format {
+        GapReportFormat::Table => reports.iter().map(|r| r.table()).collect::<String>(),
+        GapReportFormat::Csv => {
+            let mut csv = "file,marker,start_frame,end_frame,frames,type\n".to_string();
+            reports.iter().for_each(|r| csv.push_str(&r.csv()));
+            csv
+        }
+        GapReportFormat::Json => {
+            let json = Value::Array(reports.iter().map(|r| r.json()).collect());
+            serde_json::to_string_pretty(&json)
+                .map_err(|e| CliError::Processing(e.to_string()))?
+                + "\n"
+        }
+    };
+    match output {
+        Some(output) => {
+            std::fs::write(&output, report).map_err(CliError::write)?;
+            println!("Wrote {}", output.to_string_lossy().green());
+        }
+        None => print!("{}", report),
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Returns true if the marker was not tracked in this frame.
+/// C3D marks invalid points with a negative residual, but many exporters
+/// write zeros for all coordinates instead.
+pub(crate) fn is_gap(point: &MarkerPoint) -> bool {
+    point.residual < 0.0
+        || point.point == [0.0; 3]
+        || point.point.iter().any(|value| !value.is_finite())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum GapKind {
+    /// The marker was not reconstructed in these frames.
+    Invalid,
+    /// The marker has a position but a zero residual, meaning it was interpolated.
+    Interpolated,
+}
+
+impl Display for GapKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GapKind::Invalid => write!(f, "invalid"),
+            GapKind::Interpolated => write!(f, "interpolated"),
+        }
+    }
+}
+
+/// A run of consecutive frames where a marker is missing or interpolated.
+/// `start` is the row index into the point data.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Gap {
+    pub start: usize,
+    pub length: usize,
+    pub kind: GapKind,
+}
+
+impl Gap {
+    pub fn end(&self) -> usize {
+        self.start + self.length - 1
+    }
+}
+
+/// Finds the gaps in one column of point data.
+pub(crate) fn find_gaps(c3d: &C3d, column: usize) -> Vec<Gap> {
+    let mut gaps: Vec<Gap> = Vec::new();
+    for (row, point) in c3d.points.iter_col(column).enumerate() {
+        let kind = if is_gap(point) {
+            GapKind::Invalid
+        } else if point.residual == 0.0 {
+            GapKind::Interpolated
+        } else {
+            continue;
+        };
+        match gaps.last_mut() {
+            Some(gap) if gap.kind == kind && gap.end() + 1 == row => gap.length += 1,
+            _ => gaps.push(Gap {
+                start: row,
+                length: 1,
+                kind,
+            }),
+        }
+    }
+    gaps
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum GapReportFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for GapReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "table" => Ok(GapReportFormat::Table),
+            "csv" => Ok(GapReportFormat::Csv),
+            "json" => Ok(GapReportFormat::Json),
+            _ => Err(format!(
+                "{} is not a valid report format, formats allowed: table, csv, json",
+                s
+            )),
+        }
+    }
+}
+
+struct MarkerGaps {
+    label: String,
+    gaps: Vec<Gap>,
+    coverage: f64,
+}
+
+struct GapReport {
+    file: PathBuf,
+    first_frame: usize,
+    frames: usize,
+    markers: Vec<MarkerGaps>,
+}
+
+impl GapReport {
+    fn from_c3d(file: PathBuf, c3d: &C3d) -> Self {
+        let frames = c3d.points.rows();
+        let markers = (0..c3d.points.cols())
+            .map(|column| {
+                let gaps = find_gaps(c3d, column);
+                let invalid: usize = gaps
+                    .iter()
+                    .filter(|gap| gap.kind == GapKind::Invalid)
+                    .map(|gap| gap.length)
+                    .sum();
+                let coverage = match frames {
+                    0 => 0.,
+                    _ => 100. * (frames - invalid) as f64 / frames as f64,
+                };
+                MarkerGaps {
+                    label: c3d
+                        .points
+                        .labels
+                        .get(column)
+                        .cloned()
+                        .unwrap_or_else(|| format!("{}", column + 1)),
+                    gaps,
+                    coverage,
+                }
+            })
+            .collect();
+        GapReport {
+            file,
+            first_frame: c3d.points.first_frame as usize,
+            frames,
+            markers,
+        }
+    }
+
+    fn table(&self) -> String {
+        let width = self
+            .markers
+            .iter()
+            .map(|marker| marker.label.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        let mut table = format!(
+            "{} ({} frames)\n",
+            self.file.to_string_lossy(),
+            self.frames
+        );
+        table.push_str(&format!(
+            "{:<width$}  {:>8}  {:>8}  {:>8}  {}\n",
+            "Marker", "Start", "End", "Frames", "Type"
+        ));
+        for marker in &self.markers {
+            for gap in &marker.gaps {
+                table.push_str(&format!(
+                    "{:<width$}  {:>8}  {:>8}  {:>8}  {}\n",
+                    marker.label,
+                    gap.start + self.first_frame,
+                    gap.end() + self.first_frame,
+                    gap.length,
+                    gap.kind
+                ));
+            }
+        }
+        table.push('\n');
+        table.push_str(&format!(
+            "{:<width$}  {:>8}  {:>8}  {:>8}\n",
+            "Marker", "Coverage", "Gaps", "Longest"
+        ));
+        for marker in &self.markers {
+            let invalid = marker.gaps.iter().filter(|g| g.kind == GapKind::Invalid);
+            let longest = invalid.clone().map(|gap| gap.length).max().unwrap_or(0);
+            let coverage = format!("{:.1}%", marker.coverage);
+            table.push_str(&format!(
+                "{:<width$}  {:>8}  {:>8}  {:>8}\n",
+                marker.label,
+                coverage,
+                invalid.count(),
+                longest
+            ));
+        }
+        table.push('\n');
+        table
+    }
+
+    fn csv(&self) -> String {
+        let mut csv = String::new();
+        for marker in &self.markers {
+            for gap in &marker.gaps {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    self.file.to_string_lossy(),
+                    marker.label,
+                    gap.start + self.first_frame,
+                    gap.end() + self.first_frame,
+                    gap.length,
+                    gap.kind
+                ));
+            }
+        }
+        csv
+    }
+
+    fn json(&self) -> Value {
+        let markers: Vec<Value> = self
+            .markers
+            .iter()
+            .map(|marker| {
+                let gaps: Vec<Value> = marker
+                    .gaps
+                    .iter()
+                    .map(|gap| {
+                        json!({
+                            "start_frame": gap.start + self.first_frame,
+                            "end_frame": gap.end() + self.first_frame,
+                            "frames": gap.length,
+                            "type": gap.kind.to_string(),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "label": marker.label,
+                    "coverage": marker.coverage,
+                    "gaps": gaps,
+                })
+            })
+            .collect();
+        json!({
+            "file": self.file.to_string_lossy(),
+            "frames": self.frames,
+            "markers": markers,
+        })
+    }
+}
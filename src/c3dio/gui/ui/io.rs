@@ -1,4 +1,6 @@
+use crate::file_formats::sto::StoFile;
 use crate::gui::ui::notifications::Notifications;
+use crate::import::{has_ground_reactions, import_file, merge_sto, ImportFormat, MergeOptions};
 use crate::ui::notifications::Toast;
 use crate::visualizer::marker::Marker;
 use bevy::prelude::*;
@@ -15,6 +17,7 @@ impl Plugin for IoPlugin {
             (
                 load_file_dialog,
                 load_file_system,
+                import_file_system,
                 save_file_system,
                 save_as_file_system,
                 close_c3d_system,
@@ -23,7 +26,8 @@ impl Plugin for IoPlugin {
         .add_event::<CloseC3DEvent>()
         .add_event::<SaveAsFileEvent>()
         .add_event::<SaveFileEvent>()
-        .add_event::<LoadFileEvent>();
+        .add_event::<LoadFileEvent>()
+        .add_event::<ImportFileEvent>();
     }
 }
 
@@ -56,6 +60,75 @@ fn load_file_system(
     }
 }
 
+/// Imports a file, or asks for one when no path is given.
+#[derive(Event)]
+pub struct ImportFileEvent(pub Option<PathBuf>);
+
+/// Converts a TRC, Vicon CSV or Qualisys TSV file into a C3D file and opens it as if
+/// it had been loaded, or merges an STO or MOT file into the open file, as force
+/// plates if it holds ground reactions.
+fn import_file_system(
+    mut events: EventReader<ImportFileEvent>,
+    mut c3d_state: ResMut<C3dState>,
+    mut c3d_assets: ResMut<Assets<C3dAsset>>,
+    mut notifications: ResMut<Notifications>,
+) {
+    for event in events.read() {
+        let file = match &event.0 {
+            Some(path) => Some(path.clone()),
+            None => FileDialog::new()
+                .add_filter("Marker Files", &ImportFormat::EXTENSIONS[..])
+                .pick_file(),
+        };
+        let Some(file) = file else {
+            continue;
+        };
+        match ImportFormat::from_path(&file) {
+            Ok(format) if format.is_time_series() => {
+                let Some(c3d_asset) = c3d_assets.get_mut(&c3d_state.handle) else {
+                    notifications.add(Toast::error("Open a C3D file to merge the columns into"));
+                    continue;
+                };
+                let source = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let merged = StoFile::read(&file).and_then(|sto| {
+                    let options = MergeOptions {
+                        forces: has_ground_reactions(&sto),
+                        ..Default::default()
+                    };
+                    merge_sto(&mut c3d_asset.c3d, &sto, &source, &options)
+                });
+                match merged {
+                    Ok(message) => {
+                        // reloading spawns the new force plates
+                        c3d_state.loaded = false;
+                        notifications.add(Toast::success(&message));
+                    }
+                    Err(e) => notifications.add(Toast::error(&e)),
+                }
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                notifications.add(Toast::error(&e));
+                continue;
+            }
+        }
+        match import_file(&file) {
+            Ok(c3d) => {
+                // Save writes a C3D file next to the imported one
+                c3d_state.path = file.with_extension("c3d").to_string_lossy().to_string();
+                c3d_state.handle = c3d_assets.add(C3dAsset { c3d });
+                c3d_state.loaded = false;
+                notifications.add(Toast::success("File imported"));
+            }
+            Err(e) => notifications.add(Toast::error(&e)),
+        }
+    }
+}
+
 fn save_file(path: &str, c3d_state: &Res<C3dState>, c3d_assets: &Res<Assets<C3dAsset>>, notifications: &mut ResMut<Notifications>) {
         if c3d_state.loaded {
             let c3d_asset = c3d_assets.get(&c3d_state.handle);
@@ -13,7 +13,7 @@ use self::top_menu::TopMenuPlugin;
 mod analog_data;
 mod force_data;
 pub mod bottom_menu;
-mod io;
+pub mod io;
 mod marker_data;
 pub mod notifications;
 mod parameters;
@@ -21,6 +21,7 @@ mod plot;
 mod settings;
 mod tabs;
 mod three_d;
+mod tools;
 mod top_menu;
 mod windows;
 
@@ -0,0 +1,54 @@
+use crate::events::MAX_HEADER_EVENTS;
+use crate::gait::{detect_kinematic_events, write_gait_events, KinematicDetection};
+use crate::gui::ui::notifications::Notifications;
+use crate::ui::notifications::Toast;
+use bevy::prelude::*;
+use bevy_c3d::*;
+
+pub struct ToolsPlugin;
+
+impl Plugin for ToolsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, detect_gait_events_system)
+            .add_event::<DetectGaitEventsEvent>();
+    }
+}
+
+#[derive(Event)]
+pub struct DetectGaitEventsEvent;
+
+/// Replaces the foot strike and foot off events of the open file with events
+/// detected from the heel, toe and sacrum markers.
+fn detect_gait_events_system(
+    mut events: EventReader<DetectGaitEventsEvent>,
+    c3d_state: Res<C3dState>,
+    mut c3d_assets: ResMut<Assets<C3dAsset>>,
+    mut notifications: ResMut<Notifications>,
+) {
+    for _ in events.read() {
+        let Some(c3d_asset) = c3d_assets.get_mut(&c3d_state.handle) else {
+            notifications.add(Toast::error("No file loaded"));
+            continue;
+        };
+        let c3d = &mut c3d_asset.c3d;
+        // the open file is only changed once detection has succeeded
+        let events = match detect_kinematic_events(c3d, &KinematicDetection::default()) {
+            Ok(events) => events,
+            Err(e) => {
+                notifications.add(Toast::error(&e));
+                continue;
+            }
+        };
+        let dropped = write_gait_events(c3d, &events, true);
+        notifications.add(Toast::success(&format!(
+            "Detected {} gait events",
+            events.len()
+        )));
+        if dropped > 0 {
+            notifications.add(Toast::warning(&format!(
+                "The header holds {} events, the last {} were left out",
+                MAX_HEADER_EVENTS, dropped
+            )));
+        }
+    }
+}
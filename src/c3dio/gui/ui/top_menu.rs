@@ -1,5 +1,8 @@
-use super::io::{CloseC3DEvent, IoPlugin, LoadFileEvent, SaveAsFileEvent, SaveFileEvent};
+use super::io::{
+    CloseC3DEvent, ImportFileEvent, IoPlugin, LoadFileEvent, SaveAsFileEvent, SaveFileEvent,
+};
 use super::tabs::AddTabEvent;
+use super::tools::{DetectGaitEventsEvent, ToolsPlugin};
 use super::EguiTab;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
@@ -11,6 +14,7 @@ pub struct TopMenuPlugin;
 impl Plugin for TopMenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(IoPlugin)
+            .add_plugins(ToolsPlugin)
             .add_systems(Update, top_menu_system);
     }
 }
@@ -38,6 +42,7 @@ pub fn top_menu_system(world: &mut World) {
                     ui.close_menu();
                 }
                 if ui.button("Import...").clicked() {
+                    world.send_event(ImportFileEvent(None));
                     ui.close_menu();
                 }
                 if ui.button("Save").clicked() {
@@ -86,7 +91,12 @@ pub fn top_menu_system(world: &mut World) {
                     ui.close_menu();
                 }
             });
-            ui.menu_button("Tools", |ui| {});
+            ui.menu_button("Tools", |ui| {
+                if ui.button("Detect Gait Events").clicked() {
+                    world.send_event(DetectGaitEventsEvent);
+                    ui.close_menu();
+                }
+            });
             ui.menu_button("Help", |ui| {
                 if ui.button("Getting Started").clicked() {
                     ui.close_menu();
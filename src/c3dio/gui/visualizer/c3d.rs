@@ -1,4 +1,6 @@
+use crate::gui::ui::io::ImportFileEvent;
 use crate::gui::ui::notifications::Notifications;
+use crate::import::ImportFormat;
 use crate::ui::notifications::{Icon, Toast};
 use crate::visualizer::C3dFrame;
 use bevy::prelude::*;
@@ -9,11 +11,17 @@ pub fn c3d_drag_and_drop(
     mut c3d_state: ResMut<C3dState>,
     asset_server: Res<AssetServer>,
     mut notifications: ResMut<Notifications>,
+    mut import_events: EventWriter<ImportFileEvent>,
 ) {
     for event in events.read() {
         match event {
             FileDragAndDrop::DroppedFile { window, path_buf } => {
                 notifications.remove_overlay();
+                // files from other software are converted rather than loaded
+                if ImportFormat::from_path(path_buf).is_ok() {
+                    import_events.send(ImportFileEvent(Some(path_buf.clone())));
+                    continue;
+                }
                 c3d_state.path = path_buf.to_str().unwrap().to_string();
                 c3d_state.handle = asset_server.load(&c3d_state.path);
                 c3d_state.loaded = false;
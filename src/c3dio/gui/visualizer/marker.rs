@@ -1,3 +1,4 @@
+use crate::transform::point_meters_per_unit;
 use crate::visualizer::C3dFrame;
 use bevy::prelude::*;
 use bevy_c3d::*;
@@ -30,15 +31,16 @@ pub fn markers(
     match asset {
         Some(asset) => {
             let point_data = &asset.c3d.points.points;
+            let meters = point_meters_per_unit(&asset.c3d) as f32;
             let frame = c3d_frame.frame() as usize;
             if frame >= point_data.rows() {
                 return;
             }
             for (i, (mut transform, _)) in query.iter_mut().enumerate() {
                 transform.translation = Vec3::new(
-                    point_data[frame][i][0] as f32 / 1000.0,
-                    point_data[frame][i][1] as f32 / 1000.0,
-                    point_data[frame][i][2] as f32 / 1000.0,
+                    point_data[frame][i][0] as f32 * meters,
+                    point_data[frame][i][1] as f32 * meters,
+                    point_data[frame][i][2] as f32 * meters,
                 );
             }
         }
@@ -64,14 +66,15 @@ pub fn add_markers(
         for marker in markers.iter() {
             commands.entity(marker).despawn();
         }
+        let meters = point_meters_per_unit(&asset.c3d) as f32;
         for i in 0..asset.c3d.points.labels.len() {
             let matrix = Mat4::from_scale_rotation_translation(
                 Vec3::new(1.0, 1.0, 1.0),
                 Quat::from_rotation_y(0.0),
                 Vec3::new(
-                    asset.c3d.points.points[0][i][0] as f32 / 1000.0,
-                    asset.c3d.points.points[0][i][1] as f32 / 1000.0,
-                    asset.c3d.points.points[0][i][2] as f32 / 1000.0,
+                    asset.c3d.points.points[0][i][0] as f32 * meters,
+                    asset.c3d.points.points[0][i][1] as f32 * meters,
+                    asset.c3d.points.points[0][i][2] as f32 * meters,
                 ),
             );
             commands.spawn((
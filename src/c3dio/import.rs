@@ -0,0 +1,584 @@
+//! Builds C3D files from the marker formats other software writes, so legacy
+//! datasets can be used with every other command and opened in the visualizer.
+use c3dio::prelude::*;
+use c3dio::{DataFormat, ForcePlatformCorners, ForcePlatformOrigin};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use glob::Pattern;
+use grid::Grid;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::analog::{add_analog_channel, analog_rows, analog_times};
+use crate::args::{file_arg, jobs_arg, output_arg};
+use crate::batch::{find_files, finish_batch, job_count, run_batch};
+use crate::error::CliError;
+use crate::file_formats::qualisys_tsv::QualisysTsvFile;
+use crate::file_formats::sto::StoFile;
+use crate::file_formats::trc::TrcFile;
+use crate::file_formats::vicon_csv::ViconCsvFile;
+use crate::forces::MIN_VERTICAL_FORCE;
+use crate::math::{add, mat_vec, scale, transpose, Vector3};
+use crate::transform::{meters_per_unit, point_meters_per_unit, Transform};
+
+/// The size of the force plates created for imported ground reactions, in meters.
+const IMPORTED_PLATE_SIZE: [f64; 2] = [0.6, 0.4];
+
+pub(super) fn import_command() -> Command {
+    Command::new("import")
+        .about("Converts TRC, Vicon CSV and Qualisys TSV files into C3D files, or merges STO and MOT files into a C3D file")
+        .arg(
+            file_arg()
+                .required(true)
+                .help("The files to import, the format is taken from the extension"),
+        )
+        .arg(output_arg())
+        .arg(
+            Arg::new("INTO")
+                .short('i')
+                .long("into")
+                .help("The C3D file to merge STO or MOT columns into"),
+        )
+        .arg(
+            Arg::new("FORCES")
+                .long("forces")
+                .action(ArgAction::SetTrue)
+                .help("Create force plates from the ground_force columns instead of adding analog channels"),
+        )
+        .arg(
+            Arg::new("CHANNELS")
+                .short('c')
+                .long("channels")
+                .conflicts_with("FORCES")
+                .help("A comma separated list of column names or glob patterns to merge, all columns by default"),
+        )
+        .arg(
+            Arg::new("Y_UP")
+                .long("y-up")
+                .action(ArgAction::SetTrue)
+                .requires("FORCES")
+                .help("The ground reactions are Y-up, as written by forces --y-up"),
+        )
+        .arg(
+            // ground reactions are always exported in meters, the flag is kept for older scripts
+            Arg::new("METERS")
+                .long("meters")
+                .action(ArgAction::SetTrue)
+                .requires("FORCES")
+                .hide(true),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_import_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let output: PathBuf = match output {
+        Some(output) => output.into(),
+        None => {
+            // set output to current directory
+            println!(
+                "{}",
+                "No output file was provided, writing to current directory".yellow()
+            );
+            std::env::current_dir().map_err(CliError::write)?
+        }
+    };
+    let options = MergeOptions {
+        forces: sub_matches.get_flag("FORCES"),
+        channels: match sub_matches.get_one::<String>("CHANNELS") {
+            Some(channels) => channels
+                .split(',')
+                .map(|channel| channel.trim().to_string())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+            None => vec!["*".to_string()],
+        },
+        transform: Transform::export(sub_matches.get_flag("Y_UP"), sub_matches.get_flag("METERS")),
+    };
+    let files = find_files(file)?;
+    let formats = files
+        .iter()
+        .map(|file| ImportFormat::from_path(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CliError::Arguments)?;
+    let into = sub_matches.get_one::<String>("INTO");
+    if into.is_none() && formats.iter().any(|format| format.is_time_series()) {
+        return Err(CliError::Arguments(
+            "STO and MOT files have no marker data, merge them into a C3D file with --into"
+                .to_string(),
+        ));
+    }
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let c3d = match (ImportFormat::from_path(file)?, into) {
+            (format, Some(into)) if format.is_time_series() => {
+                progress.status(format!("Opening {}", into.green()));
+                // each file is merged into its own copy of the C3D file
+                let mut c3d = C3d::load(into).map_err(CliError::load)?;
+                let merged = merge_file(&mut c3d, file, &options)?;
+                progress.status(merged);
+                c3d
+            }
+            _ => import_file(file).map_err(CliError::Load)?,
+        };
+        let output = match output.is_dir() {
+            true => {
+                let mut output = output.join(file.file_name().unwrap());
+                output.set_extension("c3d");
+                output
+            }
+            false => output.clone(),
+        };
+        c3d.write_path(output.clone()).map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum ImportFormat {
+    Trc,
+    /// A Vicon Nexus trajectories export.
+    Csv,
+    /// A Qualisys Track Manager 3D export.
+    Tsv,
+    Sto,
+    Mot,
+}
+
+impl ImportFormat {
+    /// The formats that can be imported, for file dialogs.
+    pub const EXTENSIONS: [&'static str; 5] = ["trc", "csv", "tsv", "sto", "mot"];
+
+    /// STO and MOT files hold time series that are merged into an existing file.
+    pub fn is_time_series(&self) -> bool {
+        matches!(self, ImportFormat::Sto | ImportFormat::Mot)
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "trc" => Ok(ImportFormat::Trc),
+            "csv" => Ok(ImportFormat::Csv),
+            "tsv" => Ok(ImportFormat::Tsv),
+            "sto" => Ok(ImportFormat::Sto),
+            "mot" => Ok(ImportFormat::Mot),
+            _ => Err(format!(
+                "{} cannot be imported, formats allowed: trc, csv, tsv, sto, mot",
+                path.to_string_lossy()
+            )),
+        }
+    }
+}
+
+impl Display for ImportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportFormat::Trc => write!(f, "trc"),
+            ImportFormat::Csv => write!(f, "csv"),
+            ImportFormat::Tsv => write!(f, "tsv"),
+            ImportFormat::Sto => write!(f, "sto"),
+            ImportFormat::Mot => write!(f, "mot"),
+        }
+    }
+}
+
+/// Reads a TRC, Vicon CSV or Qualisys TSV file into a new C3D file.
+pub(crate) fn import_file(path: &Path) -> Result<C3d, String> {
+    match ImportFormat::from_path(path)? {
+        ImportFormat::Trc => {
+            let trc = TrcFile::read(path)?;
+            points_c3d(
+                trc.data_rate,
+                &trc.units,
+                trc.first_frame,
+                trc.marker_names,
+                trc.data,
+            )
+        }
+        ImportFormat::Csv => {
+            let csv = ViconCsvFile::read(path)?;
+            points_c3d(
+                csv.data_rate,
+                &csv.units,
+                csv.first_frame,
+                csv.marker_names,
+                csv.data,
+            )
+        }
+        ImportFormat::Tsv => {
+            let tsv = QualisysTsvFile::read(path)?;
+            points_c3d(
+                tsv.data_rate,
+                "mm",
+                tsv.first_frame,
+                tsv.marker_names,
+                tsv.data,
+            )
+        }
+        format => Err(format!(
+            "{} files have no marker data and can only be merged into a C3D file",
+            format.to_string().to_uppercase()
+        )),
+    }
+}
+
+/// Builds a C3D file holding only point data, with the parameter groups c3dio
+/// needs to write it.
+pub(crate) fn points_c3d(
+    frame_rate: f32,
+    units: &str,
+    first_frame: usize,
+    labels: Vec<String>,
+    data: Grid<MarkerPoint>,
+) -> Result<C3d, String> {
+    if data.rows() == 0 || data.cols() == 0 {
+        return Err("The file has no marker data".to_string());
+    }
+    if labels.len() != data.cols() {
+        return Err(format!(
+            "{} marker labels for {} markers",
+            labels.len(),
+            data.cols()
+        ));
+    }
+    let first_frame = first_frame.max(1);
+    let last_frame = first_frame + data.rows() - 1;
+    if last_frame > u16::MAX as usize {
+        return Err(format!(
+            "The frames end at {} but C3D headers stop at {}",
+            last_frame,
+            u16::MAX
+        ));
+    }
+    let units = units.trim();
+    if units.chars().count() > 4 {
+        return Err(format!(
+            "{} is too long for the POINT:UNITS parameter",
+            units
+        ));
+    }
+    let mut c3d = C3d::new();
+    for (group, description) in [
+        ("POINT", "3-D point parameters"),
+        ("ANALOG", "Analog data parameters"),
+        ("FORCE_PLATFORM", "Force platform parameters"),
+        ("EVENT", "Event parameters"),
+        ("EVENT_CONTEXT", "Event context parameters"),
+        ("MANUFACTURER", "Manufacturer information"),
+        ("SEG", "Segment parameters"),
+    ] {
+        c3d.parameters.insert_group(group, description.to_string());
+    }
+    let mut point_units = [' '; 4];
+    for (unit, c) in point_units.iter_mut().zip(units.chars()) {
+        *unit = c;
+    }
+    c3d.points.descriptions = vec![String::new(); labels.len()];
+    c3d.points.labels = labels;
+    c3d.points.points = data;
+    c3d.points.units = point_units;
+    c3d.points.frame_rate = frame_rate;
+    // float data keeps the coordinates exactly, the scale only applies to residuals
+    // and is 0.1 mm whatever the units
+    c3d.points.scale_factor = (0.0001 / meters_per_unit(units).unwrap_or(0.001)) as f32;
+    c3d.points.format = DataFormat::Float;
+    c3d.points.first_frame = first_frame as u16;
+    c3d.points.last_frame = last_frame as u16;
+    // no analog channels, but the rate must still be a multiple of the point rate
+    c3d.analog.rate = frame_rate;
+    c3d.analog.samples_per_channel_per_frame = 1;
+    c3d.analog.gen_scale = 1.;
+    Ok(c3d)
+}
+
+/// How the columns of an STO or MOT file are merged into a C3D file.
+#[derive(Debug, Clone)]
+pub(crate) struct MergeOptions {
+    /// Create force plates from the ground reaction columns instead of analog channels.
+    pub forces: bool,
+    /// The names or glob patterns of the columns added as analog channels.
+    pub channels: Vec<String>,
+    /// The transform the ground reactions were exported with. Its rotation is undone
+    /// when merging, the columns are always in meters.
+    pub transform: Transform,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            forces: false,
+            channels: vec!["*".to_string()],
+            transform: Transform::default(),
+        }
+    }
+}
+
+/// Merges an STO or MOT file into a C3D file, resampled at the analog rate.
+/// Returns a message saying what was added.
+pub(crate) fn merge_file(
+    c3d: &mut C3d,
+    path: &Path,
+    options: &MergeOptions,
+) -> Result<String, String> {
+    let sto = StoFile::read(path)?;
+    let source = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    merge_sto(c3d, &sto, &source, options)
+}
+
+/// Merges the columns of a storage file read from `source` into a C3D file.
+pub(crate) fn merge_sto(
+    c3d: &mut C3d,
+    sto: &StoFile,
+    source: &str,
+    options: &MergeOptions,
+) -> Result<String, String> {
+    match options.forces {
+        true => {
+            let plates = merge_forces(c3d, sto, &options.transform)?;
+            Ok(format!("Added {} force plates from {}", plates, source))
+        }
+        false => {
+            let channels = merge_analog(c3d, sto, &options.channels, source)?;
+            Ok(format!(
+                "Added {} analog channels from {}",
+                channels, source
+            ))
+        }
+    }
+}
+
+/// Returns true if the file has the ground_force columns OpenSim writes for ground reactions.
+pub(crate) fn has_ground_reactions(sto: &StoFile) -> bool {
+    !ground_reaction_prefixes(sto).is_empty()
+}
+
+/// Interpolates a column at every analog sample time. Samples outside the times of
+/// the file are zero, since analog channels cannot hold gaps.
+fn resample(c3d: &C3d, sto: &StoFile, column: usize) -> Vec<f64> {
+    analog_times(c3d)
+        .into_iter()
+        .map(|time| sto.value_at(column, time).unwrap_or(0.))
+        .collect()
+}
+
+/// Adds the columns matching any of the patterns as analog channels.
+fn merge_analog(
+    c3d: &mut C3d,
+    sto: &StoFile,
+    patterns: &[String],
+    source: &str,
+) -> Result<usize, String> {
+    let mut selected = vec![false; sto.column_names.len()];
+    for pattern in patterns {
+        let matcher = Pattern::new(pattern)
+            .map_err(|e| format!("{} is not a valid column pattern: {}", pattern, e))?;
+        let mut found = false;
+        for (column, name) in sto.column_names.iter().enumerate() {
+            if name == pattern || matcher.matches(name) {
+                selected[column] = true;
+                found = true;
+            }
+        }
+        if !found {
+            return Err(format!(
+                "{} does not match any column of {}",
+                pattern, source
+            ));
+        }
+    }
+    let description = format!("Imported from {}", source);
+    let mut added = 0;
+    for column in (0..selected.len()).filter(|column| selected[*column]) {
+        let values = resample(c3d, sto, column);
+        add_analog_channel(c3d, &sto.column_names[column], &description, "", values)?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+/// The prefixes of the ground reaction columns, such as "" for ground_force_vx
+/// or "1_" for the 1_ground_force_vx columns `build_sto` writes for each plate.
+fn ground_reaction_prefixes(sto: &StoFile) -> Vec<String> {
+    sto.column_names
+        .iter()
+        .filter_map(|name| {
+            let name = name.to_lowercase();
+            name.strip_suffix("ground_force_vx")
+                .map(|prefix| prefix.to_string())
+        })
+        .collect()
+}
+
+/// Creates a type 1 force plate for each set of ground reaction columns, the reverse
+/// of `build_sto`. Type 1 plates store the force, center of pressure and free moment
+/// directly, so the channels hold the load on the plate in the plate frame, with the
+/// plate centered on the mean center of pressure and its z axis into the floor.
+/// Assumes the vertical axis of the file is z and the columns are in meters.
+fn merge_forces(c3d: &mut C3d, sto: &StoFile, transform: &Transform) -> Result<usize, String> {
+    let prefixes = ground_reaction_prefixes(sto);
+    if prefixes.is_empty() {
+        return Err("The file has no ground_force_vx columns".to_string());
+    }
+    if analog_rows(c3d) == 0 {
+        return Err("The C3D file has no frames to add ground reactions to".to_string());
+    }
+    // undo the export rotation, taking the columns back to the lab frame of the file.
+    // ground reactions are always exported in meters, whatever the units of the transform
+    let rotation = transpose(&transform.rotation);
+    let factor = point_meters_per_unit(c3d);
+    // export transforms never translate, so lengths and moments are only rescaled
+    let to_lab = |value: Vector3, length: bool| match length {
+        true => mat_vec(&rotation, scale(value, 1. / factor)),
+        false => mat_vec(&rotation, value),
+    };
+    let mut added = 0;
+    for prefix in prefixes {
+        let column = |name: &str| {
+            let name = format!("{}{}", prefix, name);
+            sto.column(&name)
+                .ok_or(format!("The ground reactions have no {} column", name))
+        };
+        let columns = |names: [&str; 3]| -> Result<[Vec<f64>; 3], String> {
+            Ok([
+                resample(c3d, sto, column(names[0])?),
+                resample(c3d, sto, column(names[1])?),
+                resample(c3d, sto, column(names[2])?),
+            ])
+        };
+        let force = columns(["ground_force_vx", "ground_force_vy", "ground_force_vz"])?;
+        let point = columns(["ground_force_px", "ground_force_py", "ground_force_pz"])?;
+        // the free moment is optional, some files only hold forces
+        let torque = columns(["ground_torque_x", "ground_torque_y", "ground_torque_z"])
+            .unwrap_or_else(|_| {
+                [
+                    vec![0.; force[0].len()],
+                    vec![0.; force[0].len()],
+                    vec![0.; force[0].len()],
+                ]
+            });
+        let rows = force[0].len();
+        let sample = |values: &[Vec<f64>; 3], row: usize, length: bool| {
+            to_lab([values[0][row], values[1][row], values[2][row]], length)
+        };
+        let forces = (0..rows)
+            .map(|row| sample(&force, row, false))
+            .collect::<Vec<_>>();
+        let points = (0..rows)
+            .map(|row| sample(&point, row, true))
+            .collect::<Vec<_>>();
+        let torques = (0..rows)
+            .map(|row| sample(&torque, row, true))
+            .collect::<Vec<_>>();
+        let loaded = (0..rows)
+            .filter(|row| forces[*row][2].abs() >= MIN_VERTICAL_FORCE)
+            .collect::<Vec<_>>();
+        let center = match loaded.is_empty() {
+            true => [0.; 3],
+            false => scale(
+                loaded
+                    .iter()
+                    .fold([0.; 3], |sum, row| add(sum, points[*row])),
+                1. / loaded.len() as f64,
+            ),
+        };
+        let plate_number = c3d.forces.len() + 1;
+        let first_channel = c3d.analog.cols() + 1;
+        if first_channel + 5 > u8::MAX as usize {
+            return Err(
+                "FORCE_PLATFORM:CHANNEL cannot refer to more than 255 channels".to_string(),
+            );
+        }
+        let units = c3d
+            .points
+            .units
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string();
+        let torque_units = format!("N{}", units);
+        // the plate x axis is the lab x axis and its y and z axes are flipped, and the
+        // channels hold the load on the plate, the opposite of the reaction on the foot
+        let channels = [
+            ("Fx", "N", forces.iter().map(|f| -f[0]).collect::<Vec<_>>()),
+            ("Fy", "N", forces.iter().map(|f| f[1]).collect()),
+            ("Fz", "N", forces.iter().map(|f| f[2]).collect()),
+            // the center of pressure is relative to the plate center and only defined under load
+            (
+                "COPx",
+                units.as_str(),
+                (0..rows)
+                    .map(|row| match forces[row][2].abs() >= MIN_VERTICAL_FORCE {
+                        true => points[row][0] - center[0],
+                        false => 0.,
+                    })
+                    .collect(),
+            ),
+            (
+                "COPy",
+                units.as_str(),
+                (0..rows)
+                    .map(|row| match forces[row][2].abs() >= MIN_VERTICAL_FORCE {
+                        true => center[1] - points[row][1],
+                        false => 0.,
+                    })
+                    .collect(),
+            ),
+            (
+                "Tz",
+                torque_units.as_str(),
+                torques.iter().map(|t| t[2]).collect(),
+            ),
+        ];
+        for (name, channel_units, values) in channels {
+            add_analog_channel(
+                c3d,
+                &format!("FP{}_{}", plate_number, name),
+                "Imported ground reaction",
+                channel_units,
+                values,
+            )?;
+        }
+        let meters = point_meters_per_unit(c3d);
+        let (a, b) = (
+            IMPORTED_PLATE_SIZE[0] / 2. / meters,
+            IMPORTED_PLATE_SIZE[1] / 2. / meters,
+        );
+        // corner 1 is +x -y and the corners go around so the plate z axis points down
+        let mut corners = ForcePlatformCorners::new();
+        for (i, [x, y]) in [[a, -b], [-a, -b], [-a, b], [a, b]].into_iter().enumerate() {
+            corners[i] = [
+                (center[0] + x) as f32,
+                (center[1] + y) as f32,
+                center[2] as f32,
+            ];
+        }
+        let mut plate_channels = [0u8; 8];
+        for (i, channel) in plate_channels.iter_mut().take(6).enumerate() {
+            *channel = (first_channel + i) as u8;
+        }
+        if c3d.parameters.get_group("FORCE_PLATFORM").is_none() {
+            c3d.parameters
+                .insert_group("FORCE_PLATFORM", "Force platform parameters".to_string());
+        }
+        c3d.forces.push(ForcePlatform {
+            plate_type: ForcePlatformType::Type1,
+            corners,
+            origin: ForcePlatformOrigin::new(),
+            channels: plate_channels,
+            cal_matrix: None,
+        });
+        added += 1;
+    }
+    Ok(added)
+}
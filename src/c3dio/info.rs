@@ -1,27 +1,115 @@
 use std::path::PathBuf;
 
 use crate::args::file_arg;
+use crate::error::CliError;
+use crate::parameters::{all_parameters, parameters_json, plate_type_number};
 use c3dio::prelude::*;
-use clap::{ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use colored::Colorize;
+use serde_json::{json, Value};
 
 pub(super) fn info_command() -> Command {
     Command::new("info")
         .about("Prints information about a C3D file")
         .arg(file_arg().required(true))
+        .arg(
+            Arg::new("JSON")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("YAML")
+                .help("Print the information as a JSON document"),
+        )
+        .arg(
+            Arg::new("YAML")
+                .long("yaml")
+                .action(ArgAction::SetTrue)
+                .help("Print the information as a YAML document"),
+        )
 }
 
-pub(super) fn process_info_command(sub_matches: ArgMatches) {
+pub(super) fn process_info_command(sub_matches: ArgMatches) -> Result<(), CliError> {
     let file = sub_matches
         .get_one::<String>("FILE")
-        .ok_or_else(|| println!("{}", "No file was provided".red()));
-    let file = match file {
-        Ok(file) => file,
-        Err(e) => return,
-    };
-    println!("Opening {}", file.green());
-    match C3d::load(&file.clone()) {
-        Ok(c3d) => println!("{}", c3d.to_string()),
-        Err(e) => println!("{}", e.to_string().red()),
+        .ok_or(CliError::Arguments("No file was provided".to_string()))?;
+    let json = sub_matches.get_flag("JSON");
+    let yaml = sub_matches.get_flag("YAML");
+    if !json && !yaml {
+        println!("Opening {}", file.green());
     }
+    let c3d = C3d::load(&file.clone()).map_err(CliError::load)?;
+    if json {
+        let document = serde_json::to_string_pretty(&info_document(file, &c3d))
+            .map_err(|e| CliError::Processing(e.to_string()))?;
+        println!("{}", document);
+    } else if yaml {
+        let document = serde_yaml::to_string(&info_document(file, &c3d))
+            .map_err(|e| CliError::Processing(e.to_string()))?;
+        print!("{}", document);
+    } else {
+        println!("{}", c3d.to_string());
+    }
+    Ok(())
+}
+
+/// Builds a structured description of the file for machine-readable output.
+fn info_document(file: &str, c3d: &C3d) -> Value {
+    let events: Vec<Value> = c3d
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "id": event.id.iter().collect::<String>().trim_matches(char::from(0)).trim(),
+                "label": event.label,
+                "context": event.context,
+                "time": event.time,
+                "description": event.description,
+                "subject": event.subject,
+            })
+        })
+        .collect();
+    let force_platforms: Vec<Value> = c3d
+        .forces
+        .iter()
+        .map(|plate| {
+            json!({
+                "type": plate_type_number(&plate.plate_type),
+                "channels": plate.channels,
+                "corners": plate.corners.to_vec(),
+                "origin": plate.origin.to_vec(),
+            })
+        })
+        .collect();
+    json!({
+        "file": PathBuf::from(file).to_string_lossy(),
+        "header": {
+            "points_per_frame": c3d.points.cols(),
+            "analog_samples_per_frame": c3d.analog.samples_per_frame,
+            "analog_samples_per_channel_per_frame": c3d.analog.samples_per_channel_per_frame,
+            "first_frame": c3d.points.first_frame,
+            "last_frame": c3d.points.last_frame,
+            "max_interpolation_gap": c3d.points.max_interpolation_gap,
+            "scale_factor": c3d.points.scale_factor,
+            "data_format": format!("{:?}", c3d.points.format),
+            "frame_rate": c3d.points.frame_rate,
+            "supports_events_labels": c3d.events.supports_events_labels,
+        },
+        "points": {
+            "rate": c3d.points.frame_rate,
+            "frames": c3d.points.rows(),
+            "units": c3d.points.units.iter().collect::<String>().trim(),
+            "labels": c3d.points.labels,
+        },
+        "analog": {
+            "rate": c3d.analog.rate,
+            "samples": c3d.analog.rows(),
+            "labels": c3d.analog.labels,
+            "units": c3d.analog.units,
+        },
+        "force_platforms": {
+            "count": c3d.forces.len(),
+            "platforms": force_platforms,
+        },
+        "events": events,
+        "parameters": parameters_json(&all_parameters(c3d)),
+    })
 }
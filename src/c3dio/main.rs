@@ -1,12 +1,33 @@
 use bevy::prelude::*;
+use clap::error::ErrorKind;
 use clap::Command;
 
+mod analog;
+mod anonymize;
 mod args;
+mod batch;
+mod diff;
+mod emg;
+mod error;
+mod events;
+mod file_formats;
+mod fill;
+mod filter;
 mod forces;
+mod gait;
+mod gaps;
 mod gui;
+mod import;
 mod info;
 mod marker_labels;
 mod markers;
+mod math;
+mod opensim;
+mod parameters;
+mod run;
+mod transform;
+mod trim;
+mod validate;
 use gui::ui;
 use gui::visualizer;
 
@@ -15,27 +36,108 @@ fn main() {
         .version("0.6.0")
         .author("Claire V. Hammond")
         .about("A command line tool for working with C3D files")
+        .arg(args::error_format_arg())
         .subcommand(info::info_command())
         .subcommand(markers::markers_command())
         .subcommand(forces::force_command())
         .subcommand(marker_labels::marker_labels_command())
-        .get_matches();
+        .subcommand(gaps::gaps_command())
+        .subcommand(fill::fill_command())
+        .subcommand(filter::filter_command())
+        .subcommand(trim::trim_command())
+        .subcommand(validate::validate_command())
+        .subcommand(diff::diff_command())
+        .subcommand(events::events_command())
+        .subcommand(transform::transform_command())
+        .subcommand(analog::analog_command())
+        .subcommand(emg::emg_command())
+        .subcommand(run::run_command())
+        .subcommand(import::import_command())
+        .subcommand(opensim::opensim_command())
+        .subcommand(anonymize::anonymize_command())
+        .try_get_matches();
+    let matches = match matches {
+        Ok(matches) => matches,
+        Err(e) if matches!(e.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion) => {
+            e.exit()
+        }
+        Err(e) => {
+            // the matches are not available, so look for the error format in the raw arguments
+            let args = std::env::args().collect::<Vec<_>>();
+            let json = args.iter().any(|arg| arg == "--error-format=json")
+                || args
+                    .windows(2)
+                    .any(|pair| pair[0] == "--error-format" && pair[1] == "json");
+            match json {
+                true => {
+                    let message = e.to_string();
+                    let message = message.lines().next().unwrap_or_default();
+                    let message = message.trim_start_matches("error: ").to_string();
+                    error::CliError::Arguments(message).report(error::ErrorFormat::Json);
+                    std::process::exit(2);
+                }
+                false => e.exit(),
+            }
+        }
+    };
 
-    match matches.subcommand() {
+    let result = match matches.subcommand() {
         Some(("info", sub_matches)) => {
-            info::process_info_command(sub_matches.clone());
+            info::process_info_command(sub_matches.clone())
         }
         Some(("markers", sub_matches)) => {
-            markers::process_markers_command(sub_matches.clone());
+            markers::process_markers_command(sub_matches.clone())
         }
         Some(("forces", sub_matches)) => {
-            forces::process_forces_command(sub_matches.clone());
+            forces::process_forces_command(sub_matches.clone())
         }
         Some(("marker-labels", sub_matches)) => {
-            marker_labels::process_marker_labels_command(sub_matches.clone());
+            marker_labels::process_marker_labels_command(sub_matches.clone())
         }
         Some(("marker-labels", sub_matches)) => {
-            marker_labels::process_marker_labels_command(sub_matches.clone());
+            marker_labels::process_marker_labels_command(sub_matches.clone())
+        }
+        Some(("gaps", sub_matches)) => {
+            gaps::process_gaps_command(sub_matches.clone())
+        }
+        Some(("fill", sub_matches)) => {
+            fill::process_fill_command(sub_matches.clone())
+        }
+        Some(("filter", sub_matches)) => {
+            filter::process_filter_command(sub_matches.clone())
+        }
+        Some(("trim", sub_matches)) => {
+            trim::process_trim_command(sub_matches.clone())
+        }
+        Some(("validate", sub_matches)) => {
+            validate::process_validate_command(sub_matches.clone())
+        }
+        Some(("diff", sub_matches)) => {
+            diff::process_diff_command(sub_matches.clone())
+        }
+        Some(("events", sub_matches)) => {
+            events::process_events_command(sub_matches.clone())
+        }
+        Some(("transform", sub_matches)) => {
+            transform::process_transform_command(sub_matches.clone())
+        }
+        Some(("analog", sub_matches)) => {
+            analog::process_analog_command(sub_matches.clone())
+        }
+        Some(("emg", sub_matches)) => {
+            emg::process_emg_command(sub_matches.clone())
+        }
+        Some(("run", sub_matches)) => {
+            run::process_run_command(sub_matches.clone())
+        }
+        Some(("import", sub_matches)) => {
+            import::process_import_command(sub_matches.clone())
+        }
+        Some(("opensim", sub_matches)) => {
+            opensim::process_opensim_command(sub_matches.clone())
+        }
+        Some(("anonymize", sub_matches)) => {
+            anonymize::process_anonymize_command(sub_matches.clone())
         }
         _ => {
             App::new()
@@ -43,6 +145,11 @@ fn main() {
                 .add_plugins(bevy_egui::EguiPlugin)
                 .add_plugins(ui::UiPlugin)
                 .run();
+            Ok(())
         }
+    };
+    if let Err(e) = result {
+        e.report(error::error_format(&matches));
+        std::process::exit(e.exit_code());
     }
 }
@@ -1,22 +1,45 @@
 use c3dio::C3d;
-use clap::{ArgMatches, Command};
+use clap::{Arg, ArgGroup, ArgMatches, Command};
 use colored::Colorize;
-use glob::glob;
-use std::path::PathBuf;
+use grid::Grid;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::args::{file_arg, output_arg, reference_arg};
+use crate::args::{file_arg, jobs_arg, output_arg, reference_arg};
+use crate::batch::{find_files, finish_batch, job_count, run_batch};
+use crate::error::CliError;
 
 pub(super) fn marker_labels_command() -> Command {
     Command::new("marker-labels")
-        .about("Changes the marker labels in a C3D to match the labels in a comma separated list")
+        .about("Changes the marker labels in a C3D to match a label list, a mapping file or a reference order")
         .arg(file_arg().required(true))
-        .arg(reference_arg().required(true))
+        .arg(reference_arg().help(
+            "A comma separated list of labels, or a file of them, that replaces the labels by position",
+        ))
+        .arg(
+            Arg::new("MAP")
+                .short('m')
+                .long("map")
+                .conflicts_with("REFERENCE")
+                .help("A CSV (old,new) or TOML (old = \"new\") file that renames labels by name"),
+        )
+        .arg(
+            Arg::new("ORDER")
+                .long("order")
+                .help("A comma separated list of labels, or a file of them, to reorder and subset the markers to"),
+        )
+        .group(
+            ArgGroup::new("MODE")
+                .args(["REFERENCE", "MAP", "ORDER"])
+                .multiple(true)
+                .required(true),
+        )
         .arg(output_arg())
+        .arg(jobs_arg())
 }
 
-pub(super) fn process_marker_labels_command(sub_matches: ArgMatches) {
+pub(super) fn process_marker_labels_command(sub_matches: ArgMatches) -> Result<(), CliError> {
     let file = sub_matches.get_one::<String>("FILE").unwrap();
-    let reference_file = sub_matches.get_one::<String>("REFERENCE").unwrap();
     let output = sub_matches.get_one::<String>("OUTPUT");
     let output: PathBuf = match output {
         Some(output) => output.into(),
@@ -26,62 +49,200 @@ pub(super) fn process_marker_labels_command(sub_matches: ArgMatches) {
                 "{}",
                 "No output file was provided, writing to current directory".yellow()
             );
-            let output = std::env::current_dir();
-            match output {
-                Ok(output) => output,
-                Err(e) => {
-                    println!("{}", e.to_string().red());
-                    return;
-                }
-            }
+            std::env::current_dir().map_err(CliError::write)?
         }
     };
-    let reference_contents = std::fs::read_to_string(reference_file);
-    let reference_contents: String = match reference_contents {
-        Ok(reference_contents) => reference_contents,
-        Err(e) => {
-            println!("{}", e.to_string().red());
-            return;
-        }
+    let reference = match sub_matches.get_one::<String>("REFERENCE") {
+        Some(reference) => Some(label_list_value(reference)?),
+        None => None,
     };
-    println!("Opening {}", reference_file.green());
-    let reference: Vec<String> = reference_contents
-        .split(',')
-        .map(|s| s.to_string())
-        .collect();
-    let files = match glob(file) {
-        Ok(files) => files,
-        Err(e) => {
-            println!("{}", e.to_string().red());
-            return;
+    let mapping = match sub_matches.get_one::<String>("MAP") {
+        Some(map_file) => {
+            println!("Opening {}", map_file.green());
+            Some(read_label_map(Path::new(map_file)).map_err(CliError::Load)?)
         }
+        None => None,
+    };
+    let order = match sub_matches.get_one::<String>("ORDER") {
+        Some(order) => Some(label_list_value(order)?),
+        None => None,
     };
-    let files: Vec<PathBuf> = files.map(|f| f.unwrap()).collect();
-    for file in files {
-        println!("Opening {}", file.to_string_lossy().green());
-        let mut c3d = match C3d::load_path(file.clone()) {
-            Ok(c3d) => {
-                println!(
-                    "Changing {} marker labels to match {}",
-                    file.to_string_lossy().green(),
-                    reference_file.green()
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        progress.status(format!(
+            "Changing {} marker labels",
+            file.to_string_lossy().green(),
+        ));
+        if let Some(reference) = &reference {
+            set_labels(&mut c3d, reference).map_err(CliError::Arguments)?;
+        }
+        if let Some(mapping) = &mapping {
+            for label in rename_labels(&mut c3d, mapping).map_err(CliError::Arguments)? {
+                progress.println(
+                    format!("{} was not found in {}", label, file.to_string_lossy())
+                        .yellow()
+                        .to_string(),
                 );
-                c3d
-            }
-            Err(e) => {
-                println!("{}", e.to_string().red());
-                return;
             }
-        };
-        c3d.points.labels = reference.clone();
+        }
+        if let Some(order) = &order {
+            reorder_points(&mut c3d, order).map_err(CliError::Arguments)?;
+        }
         let output = match output.is_dir() {
             true => output.join(file.file_name().unwrap()),
             false => output.clone(),
         };
-        match c3d.write_path(output.clone()) {
-            Ok(_) => println!("Wrote {}", output.to_string_lossy().green()),
-            Err(e) => println!("{}", e.to_string().red()),
+        c3d.write_path(output.clone()).map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+/// Reads the labels of a `--reference` or `--order` value, from the file it names
+/// or from the value itself when it is not a file.
+fn label_list_value(value: &str) -> Result<Vec<String>, CliError> {
+    let path = Path::new(value);
+    if path.is_file() {
+        println!("Opening {}", value.green());
+        return read_label_list(path).map_err(CliError::Load);
+    }
+    let labels: Vec<String> = value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    match labels.is_empty() {
+        true => Err(CliError::Arguments(format!("{} has no labels", value))),
+        false => Ok(labels),
+    }
+}
+
+/// Reads a list of labels separated by commas or new lines.
+pub(crate) fn read_label_list(path: &Path) -> Result<Vec<String>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    Ok(contents
+        .split(|c| c == ',' || c == '\n')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect())
+}
+
+/// Reads a label mapping from a TOML file of `old = "new"` pairs
+/// or a CSV file with one `old,new` pair per line.
+pub(crate) fn read_label_map(path: &Path) -> Result<HashMap<String, String>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let is_toml = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase() == "toml")
+        .unwrap_or(false);
+    let mut mapping = HashMap::new();
+    if is_toml {
+        let table: toml::Table = contents.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        for (old, new) in table {
+            match new.as_str() {
+                Some(new) => mapping.insert(old, new.trim().to_string()),
+                None => return Err(format!("The new label for {} must be a string", old)),
+            };
+        }
+    } else {
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(',') {
+                Some((old, new)) => {
+                    mapping.insert(old.trim().to_string(), new.trim().to_string())
+                }
+                None => {
+                    return Err(format!(
+                        "Line {} of {} is not an old,new label pair",
+                        i + 1,
+                        path.to_string_lossy()
+                    ))
+                }
+            };
         }
     }
+    Ok(mapping)
 }
 
+/// Replaces the point labels by position.
+/// Fails if the number of labels does not match the number of point columns.
+pub(crate) fn set_labels(c3d: &mut C3d, labels: &[String]) -> Result<(), String> {
+    if labels.len() != c3d.points.cols() {
+        return Err(format!(
+            "{} labels were provided but the file has {} markers",
+            labels.len(),
+            c3d.points.cols()
+        ));
+    }
+    c3d.points.labels = labels.to_vec();
+    Ok(())
+}
+
+/// Renames point labels by name and returns the mapped labels that were not found.
+/// Fails if the renaming would leave two markers with the same label.
+pub(crate) fn rename_labels(
+    c3d: &mut C3d,
+    mapping: &HashMap<String, String>,
+) -> Result<Vec<String>, String> {
+    let labels: Vec<String> = c3d
+        .points
+        .labels
+        .iter()
+        .map(|label| match mapping.get(label.trim()) {
+            Some(new) => new.clone(),
+            None => label.clone(),
+        })
+        .collect();
+    for (i, label) in labels.iter().enumerate() {
+        if labels[..i].contains(label) {
+            return Err(format!("Renaming would duplicate the label {}", label));
+        }
+    }
+    let mut unused: Vec<String> = mapping
+        .keys()
+        .filter(|old| !c3d.points.labels.iter().any(|label| label.trim() == *old))
+        .cloned()
+        .collect();
+    unused.sort();
+    c3d.points.labels = labels;
+    Ok(unused)
+}
+
+/// Reorders the point columns to match `order`, dropping markers that are not listed.
+/// Fails if a listed label is not in the file or is listed twice.
+pub(crate) fn reorder_points(c3d: &mut C3d, order: &[String]) -> Result<(), String> {
+    for (i, label) in order.iter().enumerate() {
+        if order[..i].contains(label) {
+            return Err(format!("{} is listed more than once", label));
+        }
+    }
+    let mut indices = Vec::with_capacity(order.len());
+    for label in order {
+        match c3d.points.labels.iter().position(|x| x.trim() == label) {
+            Some(index) if index < c3d.points.cols() => indices.push(index),
+            _ => return Err(format!("{} is not a marker in the file", label)),
+        }
+    }
+    let mut points = Grid::new(c3d.points.rows(), indices.len());
+    for row in 0..c3d.points.rows() {
+        for (column, index) in indices.iter().enumerate() {
+            points[(row, column)] = c3d.points[(row, *index)];
+        }
+    }
+    let descriptions = &c3d.points.descriptions;
+    c3d.points.descriptions = indices
+        .iter()
+        .map(|i| descriptions.get(*i).cloned().unwrap_or_default())
+        .collect();
+    c3d.points.labels = order.to_vec();
+    c3d.points.points = points;
+    Ok(())
+}
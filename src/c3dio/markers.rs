@@ -1,12 +1,15 @@
 use c3dio::file_formats::trc;
 use c3dio::prelude::*;
-use clap::{ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use colored::Colorize;
-use glob::glob;
 use std::{fmt::Display, path::PathBuf};
 use std::str::FromStr;
 
-use crate::args::{file_arg, output_arg, format_arg};
+use crate::args::{file_arg, output_arg, format_arg, jobs_arg};
+use crate::batch::{find_files, finish_batch, job_count, run_batch};
+use crate::error::CliError;
+use crate::file_formats::csv::{Csv, GapValue};
+use crate::transform::{apply_transform, Transform};
 
 pub(super) fn markers_command() -> Command {
     Command::new("markers")
@@ -14,96 +17,123 @@ pub(super) fn markers_command() -> Command {
         .arg(file_arg().required(true))
         .arg(format_arg().required(true))
         .arg(output_arg())
+        .arg(
+            Arg::new("DELIMITER")
+                .short('d')
+                .long("delimiter")
+                .default_value(",")
+                .help("The column delimiter for csv output, a single character or \"tab\""),
+        )
+        .arg(
+            Arg::new("GAPS")
+                .long("gaps")
+                .default_value("empty")
+                .help("How missing markers are written in csv output: empty, nan or zero"),
+        )
+        .arg(
+            Arg::new("Y_UP")
+                .long("y-up")
+                .action(ArgAction::SetTrue)
+                .help("Rotate a Z-up lab frame to Y-up before writing"),
+        )
+        .arg(
+            Arg::new("METERS")
+                .long("meters")
+                .action(ArgAction::SetTrue)
+                .help("Convert positions to meters before writing"),
+        )
+        .arg(jobs_arg())
         //allow explicitly setting the output format
 }
 
-pub(super) fn process_markers_command(sub_matches: ArgMatches) {
+fn parse_delimiter(delimiter: &str) -> Result<char, String> {
+    match delimiter {
+        "tab" | "\\t" | "\t" => Ok('\t'),
+        _ => {
+            let mut chars = delimiter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(delimiter), None) => Ok(delimiter),
+                _ => Err(format!(
+                    "{} is not a valid delimiter, use a single character or \"tab\"",
+                    delimiter
+                )),
+            }
+        }
+    }
+}
+
+pub(super) fn process_markers_command(sub_matches: ArgMatches) -> Result<(), CliError> {
     let file = sub_matches.get_one::<String>("FILE").unwrap();
     let format = match sub_matches.get_one::<String>("FORMAT") {
         Some(format) => format,
         None => {
-            println!("{}", "No output format was provided".red());
-            return;
-        }
-    };
-    let format = match MarkerOutputFileTypes::from_str(format.trim().to_lowercase().as_str()) {
-        Ok(format) => format,
-        Err(e) => {
-            println!("{}", e.red());
-            return;
+            return Err(CliError::Arguments("No output format was provided".to_string()));
         }
     };
+    let format = MarkerOutputFileTypes::from_str(format.trim().to_lowercase().as_str()).map_err(CliError::Arguments)?;
+    let delimiter = sub_matches.get_one::<String>("DELIMITER").unwrap();
+    let delimiter = parse_delimiter(delimiter).map_err(CliError::Arguments)?;
+    let gap_value = sub_matches.get_one::<String>("GAPS").unwrap();
+    let gap_value = GapValue::from_str(gap_value).map_err(CliError::Arguments)?;
+    let transform = Transform::export(
+        sub_matches.get_flag("Y_UP"),
+        sub_matches.get_flag("METERS"),
+    );
     let output = sub_matches.get_one::<String>("OUTPUT");
     let output: PathBuf = match output {
         Some(output) => output.into(),
         None => {
             // set output to current directory
             println!("{}", "No output file was provided, writing to current directory".yellow());
-            let output = std::env::current_dir();
-            match output {
-                Ok(output) => output,
-                Err(e) => {
-                    println!("{}", e.to_string().red());
-                    return;
-                }
-            }
+            std::env::current_dir().map_err(CliError::write)?
         }
     };
 
     // if file contains a wildcard, use glob to find all matching files
     // make sure to check that the output file is a directory
-    let files = match glob(file) {
-        Ok(files) => files,
-        Err(e) => {
-            println!("{}", e.to_string().red());
-            return;
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        if !transform.is_identity() {
+            apply_transform(&mut c3d, &transform)?;
         }
-    };
-    let files = files
-        .filter_map(|file| match file {
-            Ok(file) => {
-                if file.is_file() {
-                    Some(file)
-                } else {
-                    None
-                }
+        progress.status(format!(
+            "Converting {} to {}",
+            file.to_string_lossy().green(),
+            format
+        ));
+        let output = match output.is_dir() {
+            true => {
+                let mut output = output.clone();
+                output.push(file.file_name().unwrap());
+                output.set_extension(format!("{}", format));
+                output
             }
-            Err(e) => {
-                println!("{}", e.to_string().red());
-                None
+            false => output.clone(),
+        };
+        let write_attempt = match format {
+            MarkerOutputFileTypes::Trc => Trc::from_c3d(&c3d).write(output.clone()),
+            MarkerOutputFileTypes::Csv => {
+                let mut csv = Csv::from_c3d(&c3d);
+                csv.delimiter = delimiter;
+                csv.gap_value = gap_value;
+                csv.write(output.clone())
             }
-        })
-        .collect::<Vec<_>>();
-    for file in files {
-        println!("Opening {}", file.to_string_lossy().green());
-        match C3d::load_path(file.clone()) {
-            Ok(c3d) => {
-                println!("Converting {} to {}", file.to_string_lossy().green(), format);
-                let output = match output.is_dir() {
-                    true => {
-                        let mut output = output.clone();
-                        output.push(file.file_name().unwrap());
-                        output.set_extension(format!("{}", format));
-                        output
-                    }
-                    false => output.clone(),
-                };
-                let write_attempt = match format {
-                    MarkerOutputFileTypes::Trc => Trc::from_c3d(&c3d).write(output.clone()),
-                };
-                match write_attempt {
-                    Ok(_) => println!("Wrote {}", output.to_string_lossy().green()),
-                    Err(e) => println!("{}", e.to_string().red()),
-                }
-            }
-            Err(e) => println!("{}", e.to_string().red()),
-        }
-    }
+        };
+        write_attempt.map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum MarkerOutputFileTypes {
     Trc,
+    Csv,
 }
 
 impl FromStr for MarkerOutputFileTypes {
@@ -112,8 +142,9 @@ impl FromStr for MarkerOutputFileTypes {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "trc" => Ok(MarkerOutputFileTypes::Trc),
+            "csv" => Ok(MarkerOutputFileTypes::Csv),
             _ => Err(format!(
-                "{} is not a valid output file type, types allowed: .trc",
+                "{} is not a valid output file type, types allowed: .trc, .csv",
                 s
             )),
         }
@@ -124,6 +155,7 @@ impl Display for MarkerOutputFileTypes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             MarkerOutputFileTypes::Trc => write!(f, "trc"),
+            MarkerOutputFileTypes::Csv => write!(f, "csv"),
         }
     }
 }
@@ -0,0 +1,64 @@
+//! Small 3D vector and matrix helpers used by the processing commands.
+
+pub(crate) type Vector3 = [f64; 3];
+
+/// A 3x3 matrix stored as rows.
+pub(crate) type Matrix3 = [[f64; 3]; 3];
+
+pub(crate) const IDENTITY: Matrix3 = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+pub(crate) fn add(a: Vector3, b: Vector3) -> Vector3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn sub(a: Vector3, b: Vector3) -> Vector3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scale(a: Vector3, s: f64) -> Vector3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub(crate) fn dot(a: Vector3, b: Vector3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: Vector3, b: Vector3) -> Vector3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn norm(a: Vector3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+/// Returns the unit vector in the direction of `a`, or `None` if `a` has no length.
+pub(crate) fn normalize(a: Vector3) -> Option<Vector3> {
+    let length = norm(a);
+    if length > f64::EPSILON {
+        Some(scale(a, 1. / length))
+    } else {
+        None
+    }
+}
+
+pub(crate) fn determinant(m: &Matrix3) -> f64 {
+    dot(m[0], cross(m[1], m[2]))
+}
+
+pub(crate) fn mat_vec(m: &Matrix3, v: Vector3) -> Vector3 {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
+
+/// The inverse of a rotation matrix.
+pub(crate) fn transpose(m: &Matrix3) -> Matrix3 {
+    from_columns(m[0], m[1], m[2])
+}
+
+/// Builds a matrix whose columns are the given axes.
+pub(crate) fn from_columns(x: Vector3, y: Vector3, z: Vector3) -> Matrix3 {
+    [[x[0], y[0], z[0]], [x[1], y[1], z[1]], [x[2], y[2], z[2]]]
+}
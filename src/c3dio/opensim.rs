@@ -0,0 +1,171 @@
+//! Writes everything OpenSim needs to run a trial: the markers for inverse
+//! kinematics and the ground reactions with the external loads that apply them.
+use c3dio::prelude::*;
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+use crate::args::{assign_arg, file_arg, jobs_arg, left_markers_arg, output_arg, right_markers_arg};
+use crate::batch::{find_files, finish_batch, job_count, run_batch};
+use crate::error::CliError;
+use crate::events::foot_assignment;
+use crate::file_formats::table::TableFormat;
+use crate::forces::{foot_reactions, foot_table};
+use crate::transform::{apply_transform, Transform};
+
+pub(super) fn opensim_command() -> Command {
+    Command::new("opensim")
+        .about("Writes a Y-up TRC in meters, a ground reaction MOT for each foot and an ExternalLoads XML for every C3D file")
+        .arg(file_arg().required(true))
+        .arg(output_arg().help("The directory to write to, the current directory by default"))
+        .arg(assign_arg())
+        .arg(left_markers_arg())
+        .arg(right_markers_arg())
+        .arg(
+            Arg::new("LEFT_BODY")
+                .long("left-body")
+                .default_value("calcn_l")
+                .help("The model body the left ground reaction is applied to"),
+        )
+        .arg(
+            Arg::new("RIGHT_BODY")
+                .long("right-body")
+                .default_value("calcn_r")
+                .help("The model body the right ground reaction is applied to"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_opensim_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let output: PathBuf = match sub_matches.get_one::<String>("OUTPUT") {
+        Some(output) => output.into(),
+        None => {
+            // set output to current directory
+            println!(
+                "{}",
+                "No output directory was provided, writing to current directory".yellow()
+            );
+            std::env::current_dir().map_err(CliError::write)?
+        }
+    };
+    if !output.is_dir() {
+        return Err(CliError::Arguments(format!(
+            "{} is not a directory, each trial is written as several files",
+            output.to_string_lossy()
+        )));
+    }
+    let assignment = foot_assignment(&sub_matches);
+    let bodies = ExternalLoadBodies {
+        left: sub_matches.get_one::<String>("LEFT_BODY").unwrap().clone(),
+        right: sub_matches.get_one::<String>("RIGHT_BODY").unwrap().clone(),
+    };
+    let transform = Transform::export(true, true);
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        apply_transform(&mut c3d, &transform)?;
+        let stem = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let trc_path = output.join(format!("{}.trc", stem));
+        Trc::from_c3d(&c3d)
+            .write(trc_path.clone())
+            .map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", trc_path.to_string_lossy().green()));
+
+        let Some(reactions) = foot_reactions(&c3d, &assignment) else {
+            progress.status(format!(
+                "{} has no force plates, only the markers were written",
+                file.to_string_lossy().yellow()
+            ));
+            return Ok(());
+        };
+        if reactions.unassigned > 0 {
+            progress.status(format!(
+                "{} plate contacts could not be assigned to a foot and were left out",
+                reactions.unassigned.to_string().yellow()
+            ));
+        }
+        let mot_path = output.join(format!("{}_grf.mot", stem));
+        foot_table(&c3d, &reactions)
+            .write(mot_path.clone(), TableFormat::Mot)
+            .map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", mot_path.to_string_lossy().green()));
+
+        let xml_path = output.join(format!("{}_ExternalLoads.xml", stem));
+        write_external_loads(&xml_path, &mot_path, &bodies).map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", xml_path.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+/// The model bodies the ground reactions of each foot are applied to.
+#[derive(Debug, Clone, PartialEq)]
+struct ExternalLoadBodies {
+    left: String,
+    right: String,
+}
+
+/// Writes an ExternalLoads file applying the `r_ground_` and `l_ground_` columns of
+/// the MOT file to the foot bodies, with forces and points in the ground frame.
+fn write_external_loads(
+    path: &Path,
+    mot_path: &Path,
+    bodies: &ExternalLoadBodies,
+) -> Result<(), std::io::Error> {
+    // OpenSim resolves the data file relative to the setup file, which sits next to it
+    let data_file = mot_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut forces = String::new();
+    for (name, body, prefix) in [
+        ("right", &bodies.right, "r_ground_"),
+        ("left", &bodies.left, "l_ground_"),
+    ] {
+        forces.push_str(&format!(
+            "\t\t\t<ExternalForce name=\"{name}\">\n\
+             \t\t\t\t<applied_to_body>{body}</applied_to_body>\n\
+             \t\t\t\t<force_expressed_in_body>ground</force_expressed_in_body>\n\
+             \t\t\t\t<point_expressed_in_body>ground</point_expressed_in_body>\n\
+             \t\t\t\t<force_identifier>{prefix}force_v</force_identifier>\n\
+             \t\t\t\t<point_identifier>{prefix}force_p</point_identifier>\n\
+             \t\t\t\t<torque_identifier>{prefix}torque_</torque_identifier>\n\
+             \t\t\t\t<data_source_name>{data_file}</data_source_name>\n\
+             \t\t\t</ExternalForce>\n",
+            name = name,
+            body = escape_xml(body),
+            prefix = prefix,
+            data_file = escape_xml(&data_file),
+        ));
+    }
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n\
+         <OpenSimDocument Version=\"40000\">\n\
+         \t<ExternalLoads name=\"externalloads\">\n\
+         \t\t<objects>\n\
+         {forces}\
+         \t\t</objects>\n\
+         \t\t<groups />\n\
+         \t\t<datafile>{data_file}</datafile>\n\
+         \t</ExternalLoads>\n\
+         </OpenSimDocument>\n",
+        forces = forces,
+        data_file = escape_xml(&data_file),
+    );
+    std::fs::write(path, xml)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,191 @@
+//! Rebuilds the complete parameter section of a C3D file.
+//!
+//! c3dio moves the POINT, ANALOG, FORCE_PLATFORM, EVENT and MANUFACTURER
+//! parameters into typed fields while parsing, so `C3d::parameters` only holds
+//! the parameters it does not understand. The functions here put them back.
+use c3dio::prelude::*;
+use serde_json::{json, Value};
+
+/// Returns every parameter of the file, including the ones c3dio parsed into typed fields.
+pub(crate) fn all_parameters(c3d: &C3d) -> Parameters {
+    let mut parameters = c3d.parameters.clone();
+    let num_frames = c3d.points.rows();
+
+    parameters.insert("POINT", "USED", Parameter::integer(c3d.points.cols() as i16));
+    parameters.insert("POINT", "FRAMES", Parameter::float(num_frames as f32));
+    parameters.insert("POINT", "RATE", Parameter::float(c3d.points.frame_rate));
+    parameters.insert("POINT", "SCALE", Parameter::float(c3d.points.scale_factor));
+    parameters.insert("POINT", "LABELS", Parameter::strings(c3d.points.labels.clone()));
+    parameters.insert(
+        "POINT",
+        "DESCRIPTIONS",
+        Parameter::strings(c3d.points.descriptions.clone()),
+    );
+    if let Ok(units) = Parameter::chars(c3d.points.units.to_vec()) {
+        parameters.insert("POINT", "UNITS", units);
+    }
+
+    parameters.insert("ANALOG", "USED", Parameter::integer(c3d.analog.cols() as i16));
+    parameters.insert("ANALOG", "RATE", Parameter::float(c3d.analog.rate));
+    parameters.insert("ANALOG", "GEN_SCALE", Parameter::float(c3d.analog.gen_scale));
+    parameters.insert("ANALOG", "BITS", Parameter::integer(c3d.analog.bits));
+    parameters.insert("ANALOG", "LABELS", Parameter::strings(c3d.analog.labels.clone()));
+    parameters.insert(
+        "ANALOG",
+        "DESCRIPTIONS",
+        Parameter::strings(c3d.analog.descriptions.clone()),
+    );
+    parameters.insert("ANALOG", "UNITS", Parameter::strings(c3d.analog.units.clone()));
+    if let Ok(scales) = Parameter::floats(c3d.analog.scales.clone()) {
+        parameters.insert("ANALOG", "SCALE", scales);
+    }
+    let offsets: Vec<i16> = match &c3d.analog.offset {
+        AnalogOffset::Signed(offset) => offset.clone(),
+        AnalogOffset::Unsigned(offset) => offset.iter().map(|x| *x as i16).collect(),
+    };
+    if let Ok(offsets) = Parameter::integers(offsets) {
+        parameters.insert("ANALOG", "OFFSET", offsets);
+    }
+
+    let plates = &c3d.forces;
+    parameters.insert("FORCE_PLATFORM", "USED", Parameter::integer(plates.len() as i16));
+    if let Ok(zero) = Parameter::integers(plates.zero.iter().map(|x| *x as i16).collect()) {
+        parameters.insert("FORCE_PLATFORM", "ZERO", zero);
+    }
+    if plates.len() > 0 {
+        let types = plates.iter().map(|plate| plate_type_number(&plate.plate_type));
+        if let Ok(types) = Parameter::integers(types.collect()) {
+            parameters.insert("FORCE_PLATFORM", "TYPE", types);
+        }
+        let channels = plates
+            .iter()
+            .flat_map(|plate| plate.channels.iter().map(|x| *x as i16))
+            .collect();
+        if let Ok(mut channels) = Parameter::integers(channels) {
+            channels.dimensions = vec![8, plates.len() as u8];
+            parameters.insert("FORCE_PLATFORM", "CHANNEL", channels);
+        }
+        let corners = plates
+            .iter()
+            .flat_map(|plate| plate.corners.iter().flatten().copied().collect::<Vec<_>>())
+            .collect();
+        if let Ok(mut corners) = Parameter::floats(corners) {
+            corners.dimensions = vec![3, 4, plates.len() as u8];
+            parameters.insert("FORCE_PLATFORM", "CORNERS", corners);
+        }
+        let origins = plates
+            .iter()
+            .flat_map(|plate| plate.origin.to_vec())
+            .collect();
+        if let Ok(mut origins) = Parameter::floats(origins) {
+            origins.dimensions = vec![3, plates.len() as u8];
+            parameters.insert("FORCE_PLATFORM", "ORIGIN", origins);
+        }
+    }
+
+    let events = &c3d.events;
+    parameters.insert("EVENT", "USED", Parameter::integer(events.len() as i16));
+    if events.len() > 0 {
+        let labels = events.iter().map(|event| event.label.clone()).collect();
+        parameters.insert("EVENT", "LABELS", Parameter::strings(labels));
+        let contexts = events.iter().map(|event| event.context.clone()).collect();
+        parameters.insert("EVENT", "CONTEXTS", Parameter::strings(contexts));
+        let descriptions = events.iter().map(|e| e.description.clone()).collect();
+        parameters.insert("EVENT", "DESCRIPTIONS", Parameter::strings(descriptions));
+        let subjects = events.iter().map(|event| event.subject.clone()).collect();
+        parameters.insert("EVENT", "SUBJECTS", Parameter::strings(subjects));
+        if let Ok(times) = Parameter::floats(events.iter().map(|event| event.time).collect()) {
+            parameters.insert("EVENT", "TIMES", times);
+        }
+    }
+
+    let manufacturer = &c3d.manufacturer;
+    if let Some(Ok(company)) = manufacturer.company.clone().map(Parameter::string) {
+        parameters.insert("MANUFACTURER", "COMPANY", company);
+    }
+    if let Some(Ok(software)) = manufacturer.software.clone().map(Parameter::string) {
+        parameters.insert("MANUFACTURER", "SOFTWARE", software);
+    }
+    match &manufacturer.version {
+        Some(ManufacturerVersion::String(version)) => {
+            if let Ok(version) = Parameter::string(version.clone()) {
+                parameters.insert("MANUFACTURER", "VERSION", version);
+            }
+        }
+        Some(ManufacturerVersion::Float(version)) => {
+            parameters.insert("MANUFACTURER", "VERSION", Parameter::float(*version));
+        }
+        _ => {}
+    }
+    if let Some(edited) = &manufacturer.edited {
+        parameters.insert("MANUFACTURER", "EDITED", Parameter::strings(edited.clone()));
+    }
+    parameters
+}
+
+pub(crate) fn plate_type_number(plate_type: &ForcePlatformType) -> i16 {
+    match plate_type {
+        ForcePlatformType::Type1 => 1,
+        ForcePlatformType::Type2 => 2,
+        ForcePlatformType::Type3 => 3,
+        ForcePlatformType::Type4 => 4,
+    }
+}
+
+/// Converts the data of a parameter to JSON.
+/// Character data becomes a string or a list of strings depending on its dimensions.
+pub(crate) fn parameter_data_json(parameter: &Parameter) -> Value {
+    match &parameter.data {
+        ParameterData::Char(_) => match parameter.dimensions.len() {
+            0 | 1 => match String::try_from(parameter) {
+                Ok(string) => json!(string),
+                Err(_) => Value::Null,
+            },
+            _ => match Vec::<String>::try_from(parameter) {
+                Ok(strings) => json!(strings),
+                Err(_) => Value::Null,
+            },
+        },
+        ParameterData::Byte(data) => json!(data),
+        ParameterData::Integer(data) => json!(data),
+        ParameterData::Float(data) => json!(data),
+    }
+}
+
+pub(crate) fn parameter_type_name(parameter: &Parameter) -> &'static str {
+    match &parameter.data {
+        ParameterData::Char(_) => "char",
+        ParameterData::Byte(_) => "byte",
+        ParameterData::Integer(_) => "integer",
+        ParameterData::Float(_) => "float",
+    }
+}
+
+/// Converts the full parameter tree to JSON, grouped by group name.
+pub(crate) fn parameters_json(parameters: &Parameters) -> Value {
+    let mut groups = serde_json::Map::new();
+    for group in parameters.groups() {
+        let mut group_parameters = serde_json::Map::new();
+        if let Some(group_map) = parameters.get_group(group) {
+            for (name, parameter) in group_map.iter() {
+                group_parameters.insert(
+                    name.clone(),
+                    json!({
+                        "description": parameter.description,
+                        "type": parameter_type_name(parameter),
+                        "dimensions": parameter.dimensions,
+                        "data": parameter_data_json(parameter),
+                    }),
+                );
+            }
+        }
+        groups.insert(
+            group.clone(),
+            json!({
+                "description": parameters.get_group_description(group).cloned().unwrap_or_default(),
+                "parameters": group_parameters,
+            }),
+        );
+    }
+    Value::Object(groups)
+}
@@ -0,0 +1,780 @@
+//! Pipelines are TOML or YAML files with an ordered list of steps that are run in memory
+//! on every input file, so a processing recipe can be versioned and shared.
+//!
+//! ```toml
+//! input = "session/*.c3d"
+//! output = "processed"
+//!
+//! [[steps]]
+//! op = "relabel"
+//! map = "labels.csv"
+//!
+//! [[steps]]
+//! op = "fill"
+//! method = "spline"
+//! max_gap = 20
+//!
+//! [[steps]]
+//! op = "write"
+//! format = "trc"
+//! ```
+//!
+//! Files named by steps, such as label maps and MVC trials, are found relative to the
+//! pipeline file, while `input` and `output` are relative to the working directory.
+use c3dio::prelude::*;
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::analog::{add_analog_channel, analog_table, select_channels};
+use crate::args::{file_arg, jobs_arg, output_arg};
+use crate::batch::{find_files, finish_batch, job_count, run_batch, Progress};
+use crate::emg::{normalize_to_mvc, process_emg, EmgPipeline, Envelope};
+use crate::error::CliError;
+use crate::events::MAX_HEADER_EVENTS;
+use crate::file_formats::csv::Csv;
+use crate::file_formats::table::TableFormat;
+use crate::fill::{fill_markers, FillMethod, FillOptions};
+use crate::filter::{filter_analog_data, filter_points_data, Butterworth};
+use crate::forces::build_sto;
+use crate::gait::{
+    detect_force_events, detect_kinematic_events, write_gait_events, FootMarkers, ForceDetection,
+    KinematicDetection, KinematicMethod,
+};
+use crate::marker_labels::{
+    read_label_list, read_label_map, rename_labels, reorder_points, set_labels,
+};
+use crate::transform::{apply_transform, meters_per_unit, parse_axes, parse_values, Transform};
+use crate::trim::{trim, TrimBound};
+
+pub(super) fn run_command() -> Command {
+    Command::new("run")
+        .about("Runs the steps of a TOML or YAML pipeline file on C3D files")
+        .arg(
+            Arg::new("PIPELINE")
+                .required(true)
+                .help("The pipeline file with the steps to run"),
+        )
+        .arg(file_arg().help("The C3D files to process, the input of the pipeline by default"))
+        .arg(
+            output_arg().help(
+                "The directory to write the outputs to, the output of the pipeline by default",
+            ),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_run_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let pipeline_file = Path::new(sub_matches.get_one::<String>("PIPELINE").unwrap());
+    println!("Opening {}", pipeline_file.to_string_lossy().green());
+    let pipeline = Pipeline::load(pipeline_file).map_err(CliError::Arguments)?;
+    let file = match sub_matches
+        .get_one::<String>("FILE")
+        .or(pipeline.input.as_ref())
+    {
+        Some(file) => file,
+        None => {
+            return Err(CliError::Arguments("No input files were provided on the command line or in the pipeline".to_string()));
+        }
+    };
+    let output: PathBuf = match sub_matches
+        .get_one::<String>("OUTPUT")
+        .or(pipeline.output.as_ref())
+    {
+        Some(output) => output.into(),
+        None => {
+            // set output to current directory
+            println!(
+                "{}",
+                "No output directory was provided, writing to current directory".yellow()
+            );
+            std::env::current_dir().map_err(CliError::write)?
+        }
+    };
+    if !output.is_dir() {
+        std::fs::create_dir_all(&output).map_err(CliError::write)?;
+    }
+    let files = find_files(file)?;
+    let jobs = job_count(
+        sub_matches
+            .get_one::<usize>("JOBS")
+            .copied()
+            .or(pipeline.jobs),
+    );
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        for (i, step) in pipeline.steps.iter().enumerate() {
+            step.apply(&mut c3d, file, &output, progress)
+                .map_err(|e| e.context(format!("Step {} ({}) failed", i + 1, step.name())))?;
+        }
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+/// The parsed pipeline file.
+pub(crate) struct Pipeline {
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub jobs: Option<usize>,
+    pub steps: Vec<Step>,
+}
+
+impl Pipeline {
+    /// Reads a pipeline from a `.toml`, `.yaml` or `.yml` file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let document = load_document(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let document = document
+            .as_object()
+            .ok_or("The pipeline must be a table of settings and steps".to_string())?;
+        check_keys(
+            document,
+            &["input", "output", "jobs", "steps"],
+            "The pipeline",
+        )?;
+        let steps = document
+            .get("steps")
+            .and_then(|steps| steps.as_array())
+            .ok_or("The pipeline has no list of steps".to_string())?
+            .iter()
+            .enumerate()
+            .map(|(i, step)| Step::parse(step, base).map_err(|e| format!("Step {}: {}", i + 1, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if steps.is_empty() {
+            return Err("The pipeline has no steps".to_string());
+        }
+        if !steps.iter().any(|step| matches!(step, Step::Write { .. })) {
+            println!(
+                "{}",
+                "The pipeline has no write step, so nothing will be saved".yellow()
+            );
+        }
+        Ok(Pipeline {
+            input: string(document, "input")?,
+            output: string(document, "output")?,
+            jobs: number(document, "jobs")?.map(|jobs| jobs as usize),
+            steps,
+        })
+    }
+}
+
+/// The formats a write step can produce.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum WriteFormat {
+    C3d,
+    Trc,
+    /// Marker trajectories as CSV.
+    Csv,
+    /// Ground reaction forces as STO.
+    Sto,
+    /// Analog channels as CSV, STO or MOT.
+    Analog(TableFormat),
+}
+
+impl WriteFormat {
+    fn extension(&self) -> String {
+        match self {
+            WriteFormat::C3d => "c3d".to_string(),
+            WriteFormat::Trc => "trc".to_string(),
+            WriteFormat::Csv => "csv".to_string(),
+            WriteFormat::Sto => "sto".to_string(),
+            WriteFormat::Analog(format) => format.to_string(),
+        }
+    }
+}
+
+/// One operation of a pipeline, parsed from a table with an `op` key.
+pub(crate) enum Step {
+    Relabel {
+        labels: Option<Vec<String>>,
+        mapping: Option<HashMap<String, String>>,
+        order: Option<Vec<String>>,
+    },
+    Fill(FillOptions),
+    Filter {
+        cutoff: f64,
+        order: usize,
+        points: bool,
+        analog: Option<Vec<String>>,
+        analog_cutoff: Option<f64>,
+    },
+    Trim {
+        start: TrimBound,
+        end: TrimBound,
+        context: Option<String>,
+    },
+    Transform(Transform),
+    Events {
+        force: Option<ForceDetection>,
+        kinematic: Option<KinematicDetection>,
+        replace: bool,
+    },
+    Emg {
+        channels: Vec<String>,
+        pipeline: EmgPipeline,
+        mvc: Option<C3d>,
+        suffix: String,
+    },
+    Write {
+        format: WriteFormat,
+        suffix: String,
+        channels: Vec<String>,
+    },
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Relabel { .. } => "relabel",
+            Step::Fill(_) => "fill",
+            Step::Filter { .. } => "filter",
+            Step::Trim { .. } => "trim",
+            Step::Transform(_) => "transform",
+            Step::Events { .. } => "events",
+            Step::Emg { .. } => "emg",
+            Step::Write { .. } => "write",
+        }
+    }
+
+    fn parse(step: &Value, base: &Path) -> Result<Self, String> {
+        let step = step
+            .as_object()
+            .ok_or("A step must be a table with an op key".to_string())?;
+        let op = string(step, "op")?.ok_or("The step has no op".to_string())?;
+        let parsed = match op.trim().to_lowercase().as_str() {
+            "relabel" => {
+                check_keys(step, &["op", "labels", "map", "order"], &op)?;
+                let labels = label_list(step, "labels", base)?;
+                let mapping = match string(step, "map")? {
+                    Some(map) => Some(read_label_map(&base.join(map))?),
+                    None => None,
+                };
+                let order = label_list(step, "order", base)?;
+                if labels.is_none() && mapping.is_none() && order.is_none() {
+                    return Err("relabel needs labels, map or order".to_string());
+                }
+                Step::Relabel {
+                    labels,
+                    mapping,
+                    order,
+                }
+            }
+            "fill" => {
+                check_keys(step, &["op", "method", "max_gap", "donor", "markers"], &op)?;
+                let method = match string(step, "method")? {
+                    Some(method) => FillMethod::from_str(&method)?,
+                    None => FillMethod::Linear,
+                };
+                let donor = string(step, "donor")?;
+                if method == FillMethod::Pattern && donor.is_none() {
+                    return Err("Pattern interpolation requires a donor marker".to_string());
+                }
+                Step::Fill(FillOptions {
+                    method,
+                    max_gap: number(step, "max_gap")?.unwrap_or(10.) as usize,
+                    donor,
+                    markers: list(step, "markers")?,
+                })
+            }
+            "filter" => {
+                check_keys(
+                    step,
+                    &["op", "cutoff", "order", "points", "analog", "analog_cutoff"],
+                    &op,
+                )?;
+                let order = number(step, "order")?.unwrap_or(2.) as usize;
+                if order == 0 {
+                    return Err("The filter order must be at least 1".to_string());
+                }
+                Step::Filter {
+                    cutoff: number(step, "cutoff")?.unwrap_or(6.),
+                    order,
+                    points: boolean(step, "points")?.unwrap_or(true),
+                    analog: list(step, "analog")?,
+                    analog_cutoff: number(step, "analog_cutoff")?,
+                }
+            }
+            "trim" => {
+                check_keys(
+                    step,
+                    &[
+                        "op",
+                        "start_frame",
+                        "end_frame",
+                        "start_time",
+                        "end_time",
+                        "start_event",
+                        "end_event",
+                        "context",
+                    ],
+                    &op,
+                )?;
+                let start = trim_bound(step, "start")?;
+                let end = trim_bound(step, "end")?;
+                if start == TrimBound::None && end == TrimBound::None {
+                    return Err("No start or end of the trimmed range was provided".to_string());
+                }
+                Step::Trim {
+                    start,
+                    end,
+                    context: string(step, "context")?,
+                }
+            }
+            "transform" => {
+                check_keys(
+                    step,
+                    &["op", "axes", "rotation", "y_up", "translate", "units"],
+                    &op,
+                )?;
+                let mut transform = Transform::default();
+                if boolean(step, "y_up")?.unwrap_or(false) {
+                    transform = Transform::export(true, false);
+                }
+                if let Some(axes) = list(step, "axes")? {
+                    transform.rotation = parse_axes(&axes.join(","))?;
+                }
+                if let Some(rotation) = list(step, "rotation")? {
+                    let values = parse_values(&rotation.join(","), 9)?;
+                    transform.rotation = [
+                        [values[0], values[1], values[2]],
+                        [values[3], values[4], values[5]],
+                        [values[6], values[7], values[8]],
+                    ];
+                }
+                if let Some(translation) = list(step, "translate")? {
+                    let values = parse_values(&translation.join(","), 3)?;
+                    transform.translation = [values[0], values[1], values[2]];
+                }
+                if let Some(units) = string(step, "units")? {
+                    if meters_per_unit(&units).is_none() {
+                        return Err(format!(
+                            "{} is not a valid unit, units allowed: mm, cm, m",
+                            units
+                        ));
+                    }
+                    transform.units = Some(units.trim().to_lowercase());
+                }
+                Step::Transform(transform)
+            }
+            "events" => {
+                check_keys(
+                    step,
+                    &[
+                        "op",
+                        "method",
+                        "threshold",
+                        "hysteresis",
+                        "left_markers",
+                        "right_markers",
+                        "sacrum",
+                        "up",
+                        "replace",
+                    ],
+                    &op,
+                )?;
+                let mut markers = FootMarkers::default();
+                if let Some(left) = list(step, "left_markers")? {
+                    markers.left = left;
+                }
+                if let Some(right) = list(step, "right_markers")? {
+                    markers.right = right;
+                }
+                let method = string(step, "method")?.unwrap_or("force".to_string());
+                let (force, kinematic) = match method.trim().to_lowercase().as_str() {
+                    "force" => {
+                        let defaults = ForceDetection::default();
+                        let force = ForceDetection {
+                            threshold: number(step, "threshold")?.unwrap_or(defaults.threshold),
+                            hysteresis: number(step, "hysteresis")?.unwrap_or(defaults.hysteresis),
+                            markers,
+                        };
+                        force.check()?;
+                        (Some(force), None)
+                    }
+                    "coordinate" | "velocity" => {
+                        let defaults = KinematicDetection::default();
+                        let up = match string(step, "up")? {
+                            Some(up) => match up.trim().to_lowercase().as_str() {
+                                "x" => 0,
+                                "y" => 1,
+                                "z" => 2,
+                                _ => {
+                                    return Err(format!(
+                                        "{} is not a valid axis, axes allowed: x, y, z",
+                                        up
+                                    ))
+                                }
+                            },
+                            None => defaults.up,
+                        };
+                        let kinematic = KinematicDetection {
+                            method: match method.trim().to_lowercase().as_str() {
+                                "velocity" => KinematicMethod::Velocity,
+                                _ => KinematicMethod::Coordinate,
+                            },
+                            markers,
+                            sacrum: list(step, "sacrum")?.unwrap_or(defaults.sacrum),
+                            up,
+                        };
+                        (None, Some(kinematic))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "{} is not a valid detection method, methods allowed: force, coordinate, velocity",
+                            method
+                        ))
+                    }
+                };
+                Step::Events {
+                    force,
+                    kinematic,
+                    replace: boolean(step, "replace")?.unwrap_or(true),
+                }
+            }
+            "emg" => {
+                check_keys(
+                    step,
+                    &[
+                        "op",
+                        "channels",
+                        "remove_dc",
+                        "band_pass",
+                        "order",
+                        "rectify",
+                        "envelope",
+                        "envelope_cutoff",
+                        "window",
+                        "mvc",
+                        "suffix",
+                    ],
+                    &op,
+                )?;
+                let defaults = EmgPipeline::default();
+                let band_pass = match list(step, "band_pass")? {
+                    Some(cutoffs) if cutoffs.len() == 1 && cutoffs[0].eq_ignore_ascii_case("none") => {
+                        None
+                    }
+                    Some(cutoffs) => match parse_values(&cutoffs.join(","), 2)?[..] {
+                        [low, high] if low < high => Some((low, high)),
+                        _ => return Err("band_pass must have the lower cutoff first".to_string()),
+                    },
+                    None => defaults.band_pass,
+                };
+                let envelope = match string(step, "envelope")?.as_deref() {
+                    Some("low-pass") | Some("lowpass") | None => {
+                        Envelope::LowPass(number(step, "envelope_cutoff")?.unwrap_or(6.))
+                    }
+                    Some("rms") => Envelope::Rms(number(step, "window")?.unwrap_or(50.) / 1000.),
+                    Some("none") => Envelope::None,
+                    Some(envelope) => {
+                        return Err(format!(
+                            "{} is not a valid envelope, envelopes allowed: low-pass, rms, none",
+                            envelope
+                        ))
+                    }
+                };
+                let mvc = match string(step, "mvc")? {
+                    Some(mvc) => Some(C3d::load_path(base.join(mvc)).map_err(|e| e.to_string())?),
+                    None => None,
+                };
+                Step::Emg {
+                    channels: list(step, "channels")?
+                        .ok_or("emg needs a list of channels".to_string())?,
+                    pipeline: EmgPipeline {
+                        remove_dc: boolean(step, "remove_dc")?.unwrap_or(defaults.remove_dc),
+                        band_pass,
+                        order: number(step, "order")?.map_or(defaults.order, |order| order as usize),
+                        rectify: boolean(step, "rectify")?.unwrap_or(defaults.rectify),
+                        envelope,
+                    },
+                    mvc,
+                    suffix: string(step, "suffix")?.unwrap_or("_ENV".to_string()),
+                }
+            }
+            "write" => {
+                check_keys(step, &["op", "format", "suffix", "channels"], &op)?;
+                let format = string(step, "format")?.unwrap_or("c3d".to_string());
+                let format = match format.trim().to_lowercase().as_str() {
+                    "c3d" => WriteFormat::C3d,
+                    "trc" => WriteFormat::Trc,
+                    "csv" => WriteFormat::Csv,
+                    "sto" => WriteFormat::Sto,
+                    "analog-csv" => WriteFormat::Analog(TableFormat::Csv),
+                    "analog-sto" => WriteFormat::Analog(TableFormat::Sto),
+                    "analog-mot" => WriteFormat::Analog(TableFormat::Mot),
+                    _ => {
+                        return Err(format!(
+                            "{} is not a valid output file type, types allowed: c3d, trc, csv, sto, analog-csv, analog-sto, analog-mot",
+                            format
+                        ))
+                    }
+                };
+                Step::Write {
+                    format,
+                    suffix: string(step, "suffix")?.unwrap_or_default(),
+                    channels: list(step, "channels")?.unwrap_or(vec!["*".to_string()]),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "{} is not a valid op, ops allowed: relabel, fill, filter, trim, transform, events, emg, write",
+                    op
+                ))
+            }
+        };
+        Ok(parsed)
+    }
+
+    /// Runs the step on a file loaded from `file`, writing outputs into `output`.
+    fn apply(
+        &self,
+        c3d: &mut C3d,
+        file: &Path,
+        output: &Path,
+        progress: &Progress,
+    ) -> Result<(), CliError> {
+        match self {
+            Step::Relabel {
+                labels,
+                mapping,
+                order,
+            } => {
+                if let Some(labels) = labels {
+                    set_labels(c3d, labels).map_err(CliError::Arguments)?;
+                }
+                if let Some(mapping) = mapping {
+                    for label in rename_labels(c3d, mapping).map_err(CliError::Arguments)? {
+                        progress.println(
+                            format!("{} was not found in {}", label, file.to_string_lossy())
+                                .yellow()
+                                .to_string(),
+                        );
+                    }
+                }
+                if let Some(order) = order {
+                    reorder_points(c3d, order).map_err(CliError::Arguments)?;
+                }
+            }
+            Step::Fill(options) => {
+                for summary in fill_markers(c3d, options).map_err(CliError::Arguments)? {
+                    progress.status(summary.to_string());
+                }
+            }
+            Step::Filter {
+                cutoff,
+                order,
+                points,
+                analog,
+                analog_cutoff,
+            } => {
+                if *points {
+                    let filter =
+                        Butterworth::low_pass(*order, *cutoff, c3d.points.frame_rate as f64)?;
+                    filter_points_data(c3d, &filter);
+                }
+                if let Some(channels) = analog {
+                    let cutoff = analog_cutoff.unwrap_or(*cutoff);
+                    let filter = Butterworth::low_pass(*order, cutoff, c3d.analog.rate as f64)?;
+                    let columns = select_channels(c3d, channels).map_err(CliError::Arguments)?;
+                    filter_analog_data(c3d, &columns, &filter);
+                }
+            }
+            Step::Trim {
+                start,
+                end,
+                context,
+            } => {
+                let start_row = start
+                    .start_row(c3d, context.as_deref())
+                    .map_err(CliError::Arguments)?;
+                let end_row = end
+                    .end_row(c3d, context.as_deref())
+                    .map_err(CliError::Arguments)?;
+                trim(c3d, start_row, end_row)?;
+            }
+            Step::Transform(transform) => apply_transform(c3d, transform)?,
+            Step::Events {
+                force,
+                kinematic,
+                replace,
+            } => {
+                let events = match (force, kinematic) {
+                    (Some(force), _) => detect_force_events(c3d, force),
+                    (_, Some(kinematic)) => {
+                        detect_kinematic_events(c3d, kinematic).map_err(CliError::Arguments)?
+                    }
+                    _ => Vec::new(),
+                };
+                progress.status(format!("Detected {} gait events", events.len()));
+                let dropped = write_gait_events(c3d, &events, *replace);
+                if dropped > 0 {
+                    progress.status(format!(
+                        "The header holds {} events, the last {} were left out",
+                        MAX_HEADER_EVENTS,
+                        dropped.to_string().yellow()
+                    ));
+                }
+            }
+            Step::Emg {
+                channels,
+                pipeline,
+                mvc,
+                suffix,
+            } => {
+                let columns = select_channels(c3d, channels).map_err(CliError::Arguments)?;
+                let mut envelopes = process_emg(c3d, &columns, pipeline)?;
+                if let Some(mvc) = mvc {
+                    normalize_to_mvc(c3d, &columns, &mut envelopes, mvc, pipeline)?;
+                }
+                for (column, values) in columns.iter().zip(envelopes) {
+                    let label = format!("{}{}", c3d.analog.labels[*column].trim(), suffix);
+                    let units = match mvc {
+                        Some(_) => "%MVC".to_string(),
+                        None => c3d.analog.units.get(*column).cloned().unwrap_or_default(),
+                    };
+                    add_analog_channel(c3d, &label, "EMG envelope", &units, values)?;
+                }
+            }
+            Step::Write {
+                format,
+                suffix,
+                channels,
+            } => {
+                let stem = file
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let output = output.join(format!("{}{}.{}", stem, suffix, format.extension()));
+                let write_attempt = match format {
+                    WriteFormat::C3d => c3d.write_path(output.clone()).map(|_| ()),
+                    WriteFormat::Trc => Trc::from_c3d(c3d).write(output.clone()),
+                    WriteFormat::Csv => Csv::from_c3d(c3d).write(output.clone()),
+                    WriteFormat::Sto => match build_sto(c3d) {
+                        Some(sto) => sto.write(output.clone()),
+                        None => Err(C3dWriteError::InvalidForcePlatformInfo(
+                            "Could not build sto file from c3d file".to_string(),
+                        )),
+                    },
+                    WriteFormat::Analog(table_format) => {
+                        let columns = select_channels(c3d, channels).map_err(CliError::Arguments)?;
+                        analog_table(c3d, &columns).write(output.clone(), *table_format)
+                    }
+                };
+                write_attempt.map_err(CliError::write)?;
+                progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a `.toml`, `.yaml` or `.yml` settings file into JSON values.
+pub(crate) fn load_document(path: &Path) -> Result<Value, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "toml" => toml::from_str(&contents).map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        _ => Err(format!(
+            "{} is not a TOML or YAML file, extensions allowed: .toml, .yaml, .yml",
+            path.to_string_lossy()
+        )),
+    }
+}
+
+/// Fails on keys the table does not know, which are most likely typos.
+pub(crate) fn check_keys(table: &Map<String, Value>, allowed: &[&str], name: &str) -> Result<(), String> {
+    match table.keys().find(|key| !allowed.contains(&key.as_str())) {
+        Some(key) => Err(format!(
+            "{} has an unknown key {}, keys allowed: {}",
+            name,
+            key,
+            allowed.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn string(table: &Map<String, Value>, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        Some(value) => Err(format!("{} must be a string, not {}", key, value)),
+    }
+}
+
+fn number(table: &Map<String, Value>, key: &str) -> Result<Option<f64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Number(value)) => Ok(value.as_f64()),
+        Some(value) => Err(format!("{} must be a number, not {}", key, value)),
+    }
+}
+
+fn boolean(table: &Map<String, Value>, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Bool(value)) => Ok(Some(*value)),
+        Some(value) => Err(format!("{} must be true or false, not {}", key, value)),
+    }
+}
+
+/// Reads a list given as an array or as a comma separated string.
+fn list(table: &Map<String, Value>, key: &str) -> Result<Option<Vec<String>>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+        )),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value.trim().to_string()),
+                Value::Number(value) => Ok(value.to_string()),
+                _ => Err(format!("{} must only hold strings or numbers", key)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some),
+        Some(value) => Err(format!("{} must be a list, not {}", key, value)),
+    }
+}
+
+/// Reads a list of labels, or the labels in a file when the value names one.
+fn label_list(
+    table: &Map<String, Value>,
+    key: &str,
+    base: &Path,
+) -> Result<Option<Vec<String>>, String> {
+    match list(table, key)? {
+        Some(labels) if labels.len() == 1 && base.join(&labels[0]).is_file() => {
+            read_label_list(&base.join(&labels[0])).map(Some)
+        }
+        labels => Ok(labels),
+    }
+}
+
+fn trim_bound(table: &Map<String, Value>, end: &str) -> Result<TrimBound, String> {
+    if let Some(frame) = number(table, &format!("{}_frame", end))? {
+        return Ok(TrimBound::Frame(frame as usize));
+    }
+    if let Some(time) = number(table, &format!("{}_time", end))? {
+        return Ok(TrimBound::Time(time as f32));
+    }
+    if let Some(label) = string(table, &format!("{}_event", end))? {
+        return Ok(TrimBound::Event(label));
+    }
+    Ok(TrimBound::None)
+}
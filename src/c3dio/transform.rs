@@ -0,0 +1,321 @@
+use c3dio::prelude::*;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use std::path::PathBuf;
+
+use crate::args::{file_arg, jobs_arg, output_arg};
+use crate::batch::{check_output, find_files, finish_batch, job_count, run_batch, suffixed_name};
+use crate::error::CliError;
+use crate::gaps::is_gap;
+use crate::math::{add, determinant, dot, mat_vec, scale, Matrix3, Vector3, IDENTITY};
+
+/// Turns a Z-up lab frame into the Y-up frame OpenSim uses: X stays, Y is the old Z
+/// and Z is the old negative Y.
+pub(crate) const Z_UP_TO_Y_UP: Matrix3 = [[1., 0., 0.], [0., 0., 1.], [0., -1., 0.]];
+
+pub(super) fn transform_command() -> Command {
+    Command::new("transform")
+        .about("Rotates, translates and rescales the points and force plates of C3D files")
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            Arg::new("AXES")
+                .short('a')
+                .long("axes")
+                .conflicts_with_all(["ROTATION", "Y_UP"])
+                .help("The old axes that become the new x, y and z axes, for example \"x,z,-y\""),
+        )
+        .arg(
+            Arg::new("ROTATION")
+                .long("rotation")
+                .conflicts_with("Y_UP")
+                .help("A rotation matrix as nine comma separated values, row by row"),
+        )
+        .arg(
+            Arg::new("Y_UP")
+                .long("y-up")
+                .action(ArgAction::SetTrue)
+                .help("Rotate a Z-up lab frame to Y-up"),
+        )
+        .arg(
+            Arg::new("TRANSLATE")
+                .short('t')
+                .long("translate")
+                .help("A translation as three comma separated values in the original units, applied after the rotation"),
+        )
+        .arg(
+            Arg::new("UNITS")
+                .short('u')
+                .long("units")
+                .help("The units to convert to: mm, cm or m"),
+        )
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_transform_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let transform = transform_from_matches(&sub_matches).map_err(CliError::Arguments)?;
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let (output, suffix): (PathBuf, &str) = match output {
+        Some(output) => (output.into(), ""),
+        None => {
+            // set output to current directory, named so the inputs are not replaced
+            println!(
+                "{}",
+                "No output file was provided, writing _transformed files to current directory".yellow()
+            );
+            (std::env::current_dir().map_err(CliError::write)?, "_transformed")
+        }
+    };
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        apply_transform(&mut c3d, &transform)?;
+        let output = match output.is_dir() {
+            true => output.join(suffixed_name(file, suffix)),
+            false => output.clone(),
+        };
+        check_output(file, &output)?;
+        c3d.write_path(output.clone()).map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+/// A change of lab frame: points move to `units` after `rotation` and then `translation`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Transform {
+    pub rotation: Matrix3,
+    /// The translation in the original units of the file.
+    pub translation: Vector3,
+    /// The units to convert to, or `None` to keep the units of the file.
+    pub units: Option<String>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            rotation: IDENTITY,
+            translation: [0.; 3],
+            units: None,
+        }
+    }
+}
+
+impl Transform {
+    /// The transform for the `--y-up` and `--meters` export options.
+    pub fn export(y_up: bool, meters: bool) -> Self {
+        Transform {
+            rotation: if y_up { Z_UP_TO_Y_UP } else { IDENTITY },
+            translation: [0.; 3],
+            units: if meters { Some("m".to_string()) } else { None },
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.rotation == IDENTITY && self.translation == [0.; 3] && self.units.is_none()
+    }
+}
+
+fn transform_from_matches(sub_matches: &ArgMatches) -> Result<Transform, String> {
+    let mut transform = Transform::default();
+    if sub_matches.get_flag("Y_UP") {
+        transform.rotation = Z_UP_TO_Y_UP;
+    }
+    if let Some(axes) = sub_matches.get_one::<String>("AXES") {
+        transform.rotation = parse_axes(axes)?;
+    }
+    if let Some(rotation) = sub_matches.get_one::<String>("ROTATION") {
+        let values = parse_values(rotation, 9)?;
+        transform.rotation = [
+            [values[0], values[1], values[2]],
+            [values[3], values[4], values[5]],
+            [values[6], values[7], values[8]],
+        ];
+    }
+    if let Some(translation) = sub_matches.get_one::<String>("TRANSLATE") {
+        let values = parse_values(translation, 3)?;
+        transform.translation = [values[0], values[1], values[2]];
+    }
+    if let Some(units) = sub_matches.get_one::<String>("UNITS") {
+        if meters_per_unit(units).is_none() {
+            return Err(format!(
+                "{} is not a valid unit, units allowed: mm, cm, m",
+                units
+            ));
+        }
+        transform.units = Some(units.trim().to_lowercase());
+    }
+    Ok(transform)
+}
+
+pub(crate) fn parse_values(values: &str, count: usize) -> Result<Vec<f64>, String> {
+    let parsed = values
+        .split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{} is not a list of numbers: {}", values, e))?;
+    match parsed.len() == count {
+        true => Ok(parsed),
+        false => Err(format!("{} must have {} values", values, count)),
+    }
+}
+
+/// Parses an axis permutation such as "x,z,-y" into the matrix that maps old to new axes.
+pub(crate) fn parse_axes(axes: &str) -> Result<Matrix3, String> {
+    let mut rotation = [[0.; 3]; 3];
+    let parts: Vec<&str> = axes.split(',').map(|part| part.trim()).collect();
+    if parts.len() != 3 {
+        return Err(format!(
+            "{} must name three axes, for example \"x,z,-y\"",
+            axes
+        ));
+    }
+    for (row, part) in parts.iter().enumerate() {
+        let (sign, axis) = match part.strip_prefix('-') {
+            Some(axis) => (-1., axis),
+            None => (1., part.strip_prefix('+').unwrap_or(part)),
+        };
+        let column = match axis.to_lowercase().as_str() {
+            "x" => 0,
+            "y" => 1,
+            "z" => 2,
+            _ => return Err(format!("{} is not an axis, axes allowed: x, y, z", part)),
+        };
+        rotation[row][column] = sign;
+    }
+    Ok(rotation)
+}
+
+/// Returns how many meters one unit is, for the POINT:UNITS values c3dio understands.
+pub(crate) fn meters_per_unit(units: &str) -> Option<f64> {
+    match units.trim().to_lowercase().as_str() {
+        "mm" => Some(0.001),
+        "cm" => Some(0.01),
+        "m" => Some(1.),
+        _ => None,
+    }
+}
+
+/// Returns how many meters one point unit of the file is, assuming millimeters if unknown.
+pub(crate) fn point_meters_per_unit(c3d: &C3d) -> f64 {
+    meters_per_unit(&c3d.points.units.iter().collect::<String>()).unwrap_or(0.001)
+}
+
+/// Moves the points and force plates into a new frame and units.
+/// Force plate moment channels are rescaled with the units so the ground reactions
+/// computed from them stay consistent.
+/// Fails unless the rotation is a proper rotation, since a reflection would turn the plates upside down.
+pub(crate) fn apply_transform(c3d: &mut C3d, transform: &Transform) -> Result<(), String> {
+    let rotation = &transform.rotation;
+    let orthonormal = (0..3).all(|i| {
+        (0..3).all(|j| {
+            let expected = if i == j { 1. } else { 0. };
+            (dot(rotation[i], rotation[j]) - expected).abs() < 1e-6
+        })
+    });
+    if !orthonormal || determinant(rotation) < 0. {
+        return Err("The rotation must be orthonormal with a determinant of 1".to_string());
+    }
+    let from_units = c3d.points.units.iter().collect::<String>();
+    let factor = match &transform.units {
+        Some(units) => {
+            let from = meters_per_unit(&from_units).ok_or(format!(
+                "The file units {} cannot be converted",
+                from_units.trim()
+            ))?;
+            let to = meters_per_unit(units).ok_or(format!("{} is not a valid unit", units))?;
+            from / to
+        }
+        None => 1.,
+    };
+    let move_point =
+        |point: Vector3| scale(add(mat_vec(rotation, point), transform.translation), factor);
+
+    for row in 0..c3d.points.rows() {
+        for column in 0..c3d.points.cols() {
+            let point = &mut c3d.points[(row, column)];
+            // missing points keep their zeros so they stay missing
+            if is_gap(point) {
+                continue;
+            }
+            let moved = move_point([point[0] as f64, point[1] as f64, point[2] as f64]);
+            point.point = [moved[0] as f32, moved[1] as f32, moved[2] as f32];
+            if point.residual > 0. {
+                point.residual *= factor as f32;
+            }
+        }
+    }
+    c3d.points.scale_factor *= factor as f32;
+
+    let mut moment_channels = Vec::new();
+    for plate in c3d.forces.iter_mut() {
+        for corner in plate.corners.iter_mut() {
+            let moved = move_point([corner[0] as f64, corner[1] as f64, corner[2] as f64]);
+            *corner = [moved[0] as f32, moved[1] as f32, moved[2] as f32];
+        }
+        // ORIGIN is in the plate frame, which follows the corners, so it is only rescaled
+        for value in plate.origin.iter_mut() {
+            *value *= factor as f32;
+        }
+        if factor == 1. {
+            continue;
+        }
+        let moments = match plate.plate_type {
+            // COP x, COP y and the free moment
+            ForcePlatformType::Type1 => 3..6,
+            ForcePlatformType::Type2 => 3..6,
+            // moments are computed from the forces and ORIGIN
+            ForcePlatformType::Type3 => 0..0,
+            ForcePlatformType::Type4 => match plate.cal_matrix.as_mut() {
+                Some(cal_matrix) => {
+                    // stored column first, so scale the moment rows of every column
+                    for column in cal_matrix.iter_mut() {
+                        for value in column[3..6].iter_mut() {
+                            *value *= factor as f32;
+                        }
+                    }
+                    0..0
+                }
+                None => 3..6,
+            },
+        };
+        moment_channels.extend(
+            plate.channels[moments]
+                .iter()
+                .filter(|c| **c > 0)
+                .map(|c| *c as usize - 1),
+        );
+    }
+    moment_channels.sort();
+    moment_channels.dedup();
+    let analog_columns = c3d.analog.cols();
+    for column in moment_channels.into_iter().filter(|c| *c < analog_columns) {
+        for row in 0..c3d.analog.rows() {
+            c3d.analog[(row, column)] *= factor;
+        }
+        // the stored counts stay the same, so integer files keep their resolution.
+        // OFFSET is in counts and is subtracted before scaling, so it does not change
+        if let Some(scale) = c3d.analog.scales.get_mut(column) {
+            *scale *= factor as f32;
+        }
+        if let (Some(unit), Some(to)) = (c3d.analog.units.get_mut(column), &transform.units) {
+            if let Some(prefix) = unit.trim().strip_suffix(from_units.trim()) {
+                *unit = format!("{}{}", prefix, to);
+            }
+        }
+    }
+
+    if let Some(units) = &transform.units {
+        let mut chars = [' '; 4];
+        for (i, c) in units.chars().take(4).enumerate() {
+            chars[i] = c;
+        }
+        c3d.points.units = chars;
+    }
+    Ok(())
+}
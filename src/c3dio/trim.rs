@@ -0,0 +1,264 @@
+use c3dio::prelude::*;
+use c3dio::Event;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use colored::Colorize;
+use grid::Grid;
+use std::path::PathBuf;
+
+use crate::args::{file_arg, jobs_arg, output_arg};
+use crate::batch::{check_output, find_files, finish_batch, job_count, run_batch, suffixed_name};
+use crate::error::CliError;
+
+pub(super) fn trim_command() -> Command {
+    Command::new("trim")
+        .about(
+            "Crops the points, analog data and events of C3D files to a frame, time or event range",
+        )
+        .arg(file_arg().required(true))
+        .arg(output_arg())
+        .arg(
+            Arg::new("START_FRAME")
+                .long("start-frame")
+                .value_parser(clap::value_parser!(usize))
+                .help("The first frame number to keep"),
+        )
+        .arg(
+            Arg::new("END_FRAME")
+                .long("end-frame")
+                .value_parser(clap::value_parser!(usize))
+                .help("The last frame number to keep"),
+        )
+        .arg(
+            Arg::new("START_TIME")
+                .long("start-time")
+                .value_parser(clap::value_parser!(f32))
+                .help("The time in seconds of the first frame to keep"),
+        )
+        .arg(
+            Arg::new("END_TIME")
+                .long("end-time")
+                .value_parser(clap::value_parser!(f32))
+                .help("The time in seconds of the last frame to keep"),
+        )
+        .arg(
+            Arg::new("START_EVENT")
+                .long("start-event")
+                .help("Keep frames from the first event with this label"),
+        )
+        .arg(
+            Arg::new("END_EVENT")
+                .long("end-event")
+                .help("Keep frames up to the last event with this label"),
+        )
+        .arg(
+            Arg::new("CONTEXT")
+                .long("context")
+                .help("Only match events with this context, for example Left or Right"),
+        )
+        .group(ArgGroup::new("START").args(["START_FRAME", "START_TIME", "START_EVENT"]))
+        .group(ArgGroup::new("END").args(["END_FRAME", "END_TIME", "END_EVENT"]))
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_trim_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let start = match (
+        sub_matches.get_one::<usize>("START_FRAME"),
+        sub_matches.get_one::<f32>("START_TIME"),
+        sub_matches.get_one::<String>("START_EVENT"),
+    ) {
+        (Some(frame), _, _) => TrimBound::Frame(*frame),
+        (_, Some(time), _) => TrimBound::Time(*time),
+        (_, _, Some(label)) => TrimBound::Event(label.clone()),
+        _ => TrimBound::None,
+    };
+    let end = match (
+        sub_matches.get_one::<usize>("END_FRAME"),
+        sub_matches.get_one::<f32>("END_TIME"),
+        sub_matches.get_one::<String>("END_EVENT"),
+    ) {
+        (Some(frame), _, _) => TrimBound::Frame(*frame),
+        (_, Some(time), _) => TrimBound::Time(*time),
+        (_, _, Some(label)) => TrimBound::Event(label.clone()),
+        _ => TrimBound::None,
+    };
+    if start == TrimBound::None && end == TrimBound::None {
+        return Err(CliError::Arguments("No start or end of the trimmed range was provided".to_string()));
+    }
+    let context = sub_matches.get_one::<String>("CONTEXT").map(|c| c.as_str());
+    let output = sub_matches.get_one::<String>("OUTPUT");
+    let (output, suffix): (PathBuf, &str) = match output {
+        Some(output) => (output.into(), ""),
+        None => {
+            // set output to current directory, named so the inputs are not replaced
+            println!(
+                "{}",
+                "No output file was provided, writing _trimmed files to current directory".yellow()
+            );
+            (std::env::current_dir().map_err(CliError::write)?, "_trimmed")
+        }
+    };
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        progress.status(format!("Opening {}", file.to_string_lossy().green()));
+        let mut c3d = C3d::load_path(file.to_path_buf()).map_err(CliError::load)?;
+        let start_row = start
+            .start_row(&c3d, context)
+            .map_err(CliError::Arguments)?;
+        let end_row = end
+            .end_row(&c3d, context)
+            .map_err(CliError::Arguments)?;
+        trim(&mut c3d, start_row, end_row)?;
+        progress.status(format!(
+            "Trimmed to frames {} to {}",
+            c3d.points.first_frame, c3d.points.last_frame
+        ));
+        let output = match output.is_dir() {
+            true => output.join(suffixed_name(file, suffix)),
+            false => output.clone(),
+        };
+        check_output(file, &output)?;
+        c3d.write_path(output.clone()).map_err(CliError::write)?;
+        progress.status(format!("Wrote {}", output.to_string_lossy().green()));
+        Ok(())
+    });
+    finish_batch(results)?;
+    Ok(())
+}
+
+/// One end of the range to keep.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TrimBound {
+    None,
+    Frame(usize),
+    Time(f32),
+    Event(String),
+}
+
+impl TrimBound {
+    pub fn start_row(&self, c3d: &C3d, context: Option<&str>) -> Result<usize, String> {
+        match self {
+            TrimBound::None => Ok(0),
+            TrimBound::Frame(frame) => frame_row(c3d, *frame),
+            TrimBound::Time(time) => frame_row(c3d, time_frame(c3d, *time)),
+            TrimBound::Event(label) => match matching_events(c3d, label, context).next() {
+                Some(event) => frame_row(c3d, time_frame(c3d, event.time)),
+                None => Err(format!("No {} event was found", label)),
+            },
+        }
+    }
+
+    pub fn end_row(&self, c3d: &C3d, context: Option<&str>) -> Result<usize, String> {
+        match self {
+            TrimBound::None => Ok(c3d.points.rows().saturating_sub(1)),
+            TrimBound::Frame(frame) => frame_row(c3d, *frame),
+            TrimBound::Time(time) => frame_row(c3d, time_frame(c3d, *time)),
+            TrimBound::Event(label) => match matching_events(c3d, label, context).last() {
+                Some(event) => frame_row(c3d, time_frame(c3d, event.time)),
+                None => Err(format!("No {} event was found", label)),
+            },
+        }
+    }
+}
+
+fn matching_events<'a>(
+    c3d: &'a C3d,
+    label: &'a str,
+    context: Option<&'a str>,
+) -> impl Iterator<Item = &'a Event> {
+    let mut events: Vec<&Event> = c3d
+        .events
+        .iter()
+        .filter(|event| event.label.trim().eq_ignore_ascii_case(label.trim()))
+        .filter(|event| match context {
+            Some(context) => event.context.trim().eq_ignore_ascii_case(context.trim()),
+            None => true,
+        })
+        .collect();
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    events.into_iter()
+}
+
+/// Returns the frame number at a time in seconds.
+/// C3D event times are measured from frame 1, which is at time zero.
+pub(crate) fn time_frame(c3d: &C3d, time: f32) -> usize {
+    (time * c3d.points.frame_rate).round().max(0.) as usize + 1
+}
+
+/// Returns the time in seconds of a frame number, the inverse of `time_frame`.
+pub(crate) fn frame_time(c3d: &C3d, frame: usize) -> f32 {
+    frame.saturating_sub(1) as f32 / c3d.points.frame_rate
+}
+
+/// Returns the point data row of a frame number.
+fn frame_row(c3d: &C3d, frame: usize) -> Result<usize, String> {
+    let first_frame = c3d.points.first_frame as usize;
+    let last_frame = first_frame + c3d.points.rows().saturating_sub(1);
+    if frame < first_frame || frame > last_frame {
+        return Err(format!(
+            "Frame {} is outside the frames of the file, {} to {}",
+            frame, first_frame, last_frame
+        ));
+    }
+    Ok(frame - first_frame)
+}
+
+fn crop_rows<T: Clone>(grid: &Grid<T>, start: usize, end: usize) -> Grid<T> {
+    let data: Vec<T> = (start..end)
+        .flat_map(|row| grid.iter_row(row).cloned().collect::<Vec<_>>())
+        .collect();
+    Grid::from_vec(data, grid.cols())
+}
+
+/// Keeps the point rows from `start_row` to `end_row` inclusive, along with the
+/// matching analog samples and the events inside the range.
+/// The first and last frame numbers, and the TRIAL fields when the file has them,
+/// are updated so the written header stays consistent.
+pub(crate) fn trim(c3d: &mut C3d, start_row: usize, end_row: usize) -> Result<(), String> {
+    if start_row > end_row || end_row >= c3d.points.rows() {
+        return Err(format!(
+            "The range of rows {} to {} is not inside the {} frames of the file",
+            start_row,
+            end_row,
+            c3d.points.rows()
+        ));
+    }
+    let first_frame = c3d.points.first_frame as usize + start_row;
+    let last_frame = c3d.points.first_frame as usize + end_row;
+    if last_frame > u16::MAX as usize {
+        return Err(format!(
+            "Frame {} is too large for the C3D header",
+            last_frame
+        ));
+    }
+
+    c3d.points.points = crop_rows(&c3d.points, start_row, end_row + 1);
+    let samples = c3d.analog.samples_per_channel_per_frame as usize;
+    if c3d.analog.cols() > 0 && samples > 0 {
+        let analog_end = ((end_row + 1) * samples).min(c3d.analog.rows());
+        c3d.analog.analog = crop_rows(&c3d.analog, start_row * samples, analog_end);
+    }
+
+    let start_time = frame_time(c3d, first_frame) - 0.5 / c3d.points.frame_rate;
+    let end_time = frame_time(c3d, last_frame) + 0.5 / c3d.points.frame_rate;
+    c3d.events
+        .retain(|event| event.time >= start_time && event.time < end_time);
+
+    c3d.points.first_frame = first_frame as u16;
+    c3d.points.last_frame = last_frame as u16;
+    // readers check the frame count against these, stored as low and high 16 bit words
+    for (name, frame) in [
+        ("ACTUAL_START_FIELD", first_frame),
+        ("ACTUAL_END_FIELD", last_frame),
+    ] {
+        if let Some(parameter) = c3d.parameters.get_mut("TRIAL", name) {
+            parameter.dimensions = vec![2];
+            parameter.data = ParameterData::Integer(vec![
+                (frame & 0xffff) as u16 as i16,
+                (frame >> 16) as u16 as i16,
+            ]);
+        }
+    }
+    Ok(())
+}
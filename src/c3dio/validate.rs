@@ -0,0 +1,312 @@
+use c3dio::prelude::*;
+use clap::{ArgMatches, Command};
+use colored::Colorize;
+use std::fmt::Display;
+
+use crate::args::{file_arg, jobs_arg};
+use crate::batch::{find_files, job_count, run_batch};
+use crate::error::CliError;
+use crate::events::MAX_HEADER_EVENTS;
+use crate::math::{cross, dot, norm, normalize, sub, Vector3};
+
+/// The largest distance of the fourth corner from the plane of the other three,
+/// as a fraction of the plate diagonal, before the corners count as non-planar.
+const PLANARITY_TOLERANCE: f64 = 0.01;
+
+pub(super) fn validate_command() -> Command {
+    Command::new("validate")
+        .about("Checks C3D files for inconsistent or invalid data, exiting with 1 on warnings and 7 on errors")
+        .arg(file_arg().required(true))
+        .arg(jobs_arg())
+}
+
+pub(super) fn process_validate_command(sub_matches: ArgMatches) -> Result<(), CliError> {
+    let file = sub_matches.get_one::<String>("FILE").unwrap();
+    let files = find_files(file)?;
+    let jobs = job_count(sub_matches.get_one::<usize>("JOBS").copied());
+    let results = run_batch(&files, jobs, |file, progress| {
+        let issues = match C3d::load_path(file.to_path_buf()) {
+            Ok(c3d) => validate(&c3d),
+            Err(e) => vec![Issue::error(e.to_string())],
+        };
+        // print each file in one go so the reports of parallel files do not interleave
+        let mut report = format!("Validating {}", file.to_string_lossy().green());
+        if issues.is_empty() {
+            report.push_str(&format!("\n  {}", "no problems found".green()));
+        }
+        for issue in &issues {
+            report.push_str(&format!("\n  {}", issue));
+        }
+        progress.println(report);
+        Ok(issues.iter().map(|issue| issue.severity).max())
+    });
+    let worst = results
+        .iter()
+        .filter_map(|(_, worst)| worst.as_ref().ok().copied().flatten())
+        .collect::<Vec<_>>();
+    let count = |severity| worst.iter().filter(|worst| **worst == severity).count();
+    match worst.iter().max() {
+        Some(Severity::Error) => Err(CliError::Invalid(format!(
+            "{} of {} files have errors",
+            count(Severity::Error),
+            files.len()
+        ))),
+        Some(Severity::Warning) => Err(CliError::Findings(format!(
+            "{} of {} files have warnings",
+            count(Severity::Warning),
+            files.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    fn warning(message: String) -> Self {
+        Issue {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Issue {
+            severity: Severity::Error,
+            message,
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "{}: {}", "warning".yellow(), self.message),
+            Severity::Error => write!(f, "{}: {}", "error".red(), self.message),
+        }
+    }
+}
+
+/// Runs every check on a loaded file and returns the problems found.
+pub(crate) fn validate(c3d: &C3d) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    check_labels(&mut issues, "point", &c3d.points.labels, c3d.points.cols());
+    check_labels(&mut issues, "analog", &c3d.analog.labels, c3d.analog.cols());
+    check_analog_scaling(&mut issues, c3d);
+    check_rates(&mut issues, c3d);
+    check_frame_counts(&mut issues, c3d);
+    check_force_platforms(&mut issues, c3d);
+    check_values(&mut issues, c3d);
+    if c3d.events.len() > MAX_HEADER_EVENTS {
+        issues.push(Issue::warning(format!(
+            "{} events are stored but the header only holds {}",
+            c3d.events.len(),
+            MAX_HEADER_EVENTS
+        )));
+    }
+    issues
+}
+
+fn check_labels(issues: &mut Vec<Issue>, kind: &str, labels: &[String], columns: usize) {
+    if labels.len() < columns {
+        issues.push(Issue::error(format!(
+            "{} {} labels for {} {} columns",
+            labels.len(),
+            kind,
+            columns,
+            kind
+        )));
+    } else if labels.len() > columns {
+        issues.push(Issue::warning(format!(
+            "{} {} labels for {} {} columns",
+            labels.len(),
+            kind,
+            columns,
+            kind
+        )));
+    }
+    for (i, label) in labels.iter().enumerate() {
+        if label.trim().is_empty() {
+            issues.push(Issue::warning(format!("{} label {} is empty", kind, i + 1)));
+        } else if labels[..i].iter().any(|other| other.trim() == label.trim()) {
+            issues.push(Issue::error(format!(
+                "{} label {} is duplicated",
+                kind,
+                label.trim()
+            )));
+        }
+    }
+}
+
+fn check_analog_scaling(issues: &mut Vec<Issue>, c3d: &C3d) {
+    let columns = c3d.analog.cols();
+    if columns == 0 {
+        return;
+    }
+    if c3d.analog.scales.len() < columns {
+        issues.push(Issue::error(format!(
+            "ANALOG:SCALE has {} values for {} analog channels",
+            c3d.analog.scales.len(),
+            columns
+        )));
+    }
+    let offsets = match &c3d.analog.offset {
+        AnalogOffset::Signed(offset) => offset.len(),
+        AnalogOffset::Unsigned(offset) => offset.len(),
+    };
+    if offsets < columns {
+        issues.push(Issue::error(format!(
+            "ANALOG:OFFSET has {} values for {} analog channels",
+            offsets, columns
+        )));
+    }
+}
+
+fn check_rates(issues: &mut Vec<Issue>, c3d: &C3d) {
+    let point_rate = c3d.points.frame_rate;
+    if !(point_rate > 0.) {
+        issues.push(Issue::error(format!(
+            "The point rate {} is not positive",
+            point_rate
+        )));
+        return;
+    }
+    if c3d.analog.cols() == 0 {
+        return;
+    }
+    let ratio = c3d.analog.rate / point_rate;
+    if (ratio - ratio.round()).abs() > 1e-3 || ratio.round() < 1. {
+        issues.push(Issue::error(format!(
+            "The analog rate {} Hz is not a multiple of the point rate {} Hz",
+            c3d.analog.rate, point_rate
+        )));
+    } else if ratio.round() as u16 != c3d.analog.samples_per_channel_per_frame {
+        issues.push(Issue::warning(format!(
+            "The analog rate implies {} samples per frame but the file stores {}",
+            ratio.round(),
+            c3d.analog.samples_per_channel_per_frame
+        )));
+    }
+}
+
+fn check_frame_counts(issues: &mut Vec<Issue>, c3d: &C3d) {
+    let frames = c3d.points.rows();
+    let header_frames =
+        (c3d.points.last_frame as usize + 1).saturating_sub(c3d.points.first_frame as usize);
+    if header_frames != frames {
+        issues.push(Issue::warning(format!(
+            "The header spans {} frames ({} to {}) but the file has {} frames of data",
+            header_frames, c3d.points.first_frame, c3d.points.last_frame, frames
+        )));
+    }
+    if c3d.analog.cols() > 0 {
+        let expected = frames * c3d.analog.samples_per_channel_per_frame as usize;
+        if c3d.analog.rows() != expected {
+            issues.push(Issue::error(format!(
+                "The file has {} analog samples per channel but {} frames need {}",
+                c3d.analog.rows(),
+                frames,
+                expected
+            )));
+        }
+    }
+}
+
+fn check_force_platforms(issues: &mut Vec<Issue>, c3d: &C3d) {
+    for (i, plate) in c3d.forces.iter().enumerate() {
+        let needed = match plate.plate_type {
+            ForcePlatformType::Type3 => 8,
+            _ => 6,
+        };
+        for (j, channel) in plate.channels.iter().take(needed).enumerate() {
+            if *channel == 0 {
+                issues.push(Issue::error(format!(
+                    "Force platform {} has no analog channel for input {}",
+                    i + 1,
+                    j + 1
+                )));
+            } else if *channel as usize > c3d.analog.cols() {
+                issues.push(Issue::error(format!(
+                    "Force platform {} uses channel {} but there are {} analog channels",
+                    i + 1,
+                    channel,
+                    c3d.analog.cols()
+                )));
+            }
+        }
+        let corners: Vec<Vector3> = plate
+            .corners
+            .iter()
+            .map(|c| [c[0] as f64, c[1] as f64, c[2] as f64])
+            .collect();
+        let normal = normalize(cross(
+            sub(corners[1], corners[0]),
+            sub(corners[2], corners[0]),
+        ));
+        match normal {
+            None => issues.push(Issue::error(format!(
+                "Force platform {} has degenerate corners",
+                i + 1
+            ))),
+            Some(normal) => {
+                let diagonal = norm(sub(corners[2], corners[0]));
+                let distance = dot(sub(corners[3], corners[0]), normal).abs();
+                if distance > PLANARITY_TOLERANCE * diagonal {
+                    issues.push(Issue::warning(format!(
+                        "Force platform {} corners are not planar, the fourth corner is {:.3} off the plane",
+                        i + 1,
+                        distance
+                    )));
+                }
+            }
+        }
+    }
+}
+
+fn check_values(issues: &mut Vec<Issue>, c3d: &C3d) {
+    for column in 0..c3d.points.cols() {
+        let count = c3d
+            .points
+            .iter_col(column)
+            .filter(|p| p.point.iter().any(|v| !v.is_finite()) || !p.residual.is_finite())
+            .count();
+        if count > 0 {
+            issues.push(Issue::error(format!(
+                "Marker {} has {} frames with NaN or infinite values",
+                column_label(&c3d.points.labels, column),
+                count
+            )));
+        }
+    }
+    for column in 0..c3d.analog.cols() {
+        let count = c3d
+            .analog
+            .iter_col(column)
+            .filter(|v| !v.is_finite())
+            .count();
+        if count > 0 {
+            issues.push(Issue::error(format!(
+                "Analog channel {} has {} samples with NaN or infinite values",
+                column_label(&c3d.analog.labels, column),
+                count
+            )));
+        }
+    }
+}
+
+fn column_label(labels: &[String], column: usize) -> String {
+    match labels.get(column) {
+        Some(label) if !label.trim().is_empty() => label.trim().to_string(),
+        _ => format!("{}", column + 1),
+    }
+}